@@ -0,0 +1,133 @@
+use aws_sdk_sts::error::ProvideErrorMetadata;
+use std::fmt::{self, Display};
+use std::io::ErrorKind;
+
+use crate::utils::document::ParseError;
+
+/// 本ツールのエラー
+/// 種類ごとに終了コードを固定し、ラップするスクリプトで失敗の原因を判別できるようにする
+#[derive(Debug)]
+pub enum AppError {
+    /// プロンプトでの入力や選択がキャンセルされた
+    Cancelled,
+    /// ファイルの読み書きや外部コマンドの実行に失敗した
+    File(String),
+    /// ファイルや設定値の形式が不正
+    Parse(String),
+    /// プロファイルが存在しない、組み合わせられない設定があるなど
+    Validation(String),
+    /// STSやSSOへのリクエストが拒否された、または失敗した
+    Sts(String),
+    /// プロンプトを表示できない(TTYがないなど)
+    Prompt(String),
+    /// 上記に分類できないエラー
+    Other(String),
+}
+
+impl AppError {
+    /// 終了コード
+    /// スクリプトから判別するため、値は変更しない
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Other(_) => 1,
+            AppError::Validation(_) => 2,
+            AppError::File(_) => 3,
+            AppError::Parse(_) => 4,
+            AppError::Sts(_) => 5,
+            AppError::Prompt(_) => 6,
+            // SIGINTで終了した場合と同じ値にする
+            AppError::Cancelled => 130,
+        }
+    }
+
+    /// `Box<dyn Error>`で伝搬してきたエラーを種類ごとに分類
+    pub fn classify(error: Box<dyn std::error::Error>) -> AppError {
+        let error = match error.downcast::<AppError>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return AppError::from_io(*error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<dialoguer::Error>() {
+            Ok(error) => return AppError::from_prompt(*error),
+            Err(error) => error,
+        };
+        if error.is::<ParseError>() || error.is::<serde_json::Error>() {
+            return AppError::Parse(error.to_string());
+        }
+        if let Some(e) = error.downcast_ref::<aws_sdk_sts::Error>() {
+            return AppError::Sts(sdk_message("STS", e));
+        }
+        if let Some(e) = error.downcast_ref::<aws_sdk_sso::Error>() {
+            return AppError::Sts(sdk_message("SSO", e));
+        }
+        if let Some(e) = error.downcast_ref::<aws_sdk_ssooidc::Error>() {
+            return AppError::Sts(sdk_message("SSO", e));
+        }
+        AppError::Other(error.to_string())
+    }
+
+    /// ファイル操作のエラーを変換
+    /// Ctrl+Cで中断された場合はキャンセルとして扱う
+    pub fn from_io(error: std::io::Error) -> AppError {
+        match error.kind() {
+            ErrorKind::Interrupted => AppError::Cancelled,
+            _ => AppError::File(error.to_string()),
+        }
+    }
+
+    /// プロンプトのエラーを変換
+    /// Ctrl+Cで中断された場合はキャンセルとして扱う
+    pub fn from_prompt(error: dialoguer::Error) -> AppError {
+        let dialoguer::Error::IO(error) = error;
+        match error.kind() {
+            ErrorKind::Interrupted => AppError::Cancelled,
+            _ => AppError::Prompt(format!("Oops... cannot show the prompt: {}..", error)),
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Cancelled => write!(f, "Cancelled."),
+            AppError::File(msg)
+            | AppError::Parse(msg)
+            | AppError::Validation(msg)
+            | AppError::Sts(msg)
+            | AppError::Prompt(msg)
+            | AppError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// SDKのエラーを表示用の文字列に変換
+/// サービスのエラーコードがない場合(通信の失敗など)は原因のエラーまで含める
+fn sdk_message<E>(service: &str, error: &E) -> String
+where
+    E: ProvideErrorMetadata + std::error::Error,
+{
+    match (error.code(), error.message()) {
+        (Some(code), Some(message)) => {
+            format!(
+                "Oops... {} rejected the request: {} ({})..",
+                service, message, code
+            )
+        }
+        (Some(code), None) => format!("Oops... {} rejected the request: {}..", service, code),
+        _ => {
+            // 原因のエラーを順に連結する
+            let mut message = error.to_string();
+            let mut source = error.source();
+            while let Some(e) = source {
+                message = format!("{}: {}", message, e);
+                source = e.source();
+            }
+            format!("Oops... {} request failed: {}..", service, message)
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
+mod error;
 mod profile;
+mod sso_client;
 mod sts_client;
 mod utils;
 
@@ -20,6 +23,29 @@ struct Cli {
     /// Forces the session token to be updated.
     #[clap(short, long)]
     force: bool,
+
+    /// Duration of the session such as `3600`, `90m` or `12h` (default: `duration_seconds` of the profile)
+    #[clap(short, long, value_parser = utils::duration::parse_seconds)]
+    duration: Option<i32>,
+
+    /// MFA code for the request (`-` reads stdin, default: prompt or `mfa_process` of the profile)
+    #[clap(long, value_name = "CODE")]
+    token_code: Option<String>,
+
+    #[clap(flatten)]
+    role: RoleArgs,
+
+    /// Directory of the AWS files (default: `~/.aws`, or `AWS_CONFIG_FILE` / `AWS_SHARED_CREDENTIALS_FILE`)
+    #[clap(long, global = true)]
+    aws_dir: Option<PathBuf>,
+
+    /// Shows the changes to the AWS files as a diff without writing them
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Restricts the permissions of the AWS files and the tool file to the owner
+    #[clap(long)]
+    fix_permissions: bool,
 }
 
 // サブコマンドに対する処理
@@ -36,6 +62,25 @@ enum CliSubCommand {
         /// Forces the session token to be updated.
         #[clap(short, long)]
         force: bool,
+
+        /// Duration of the session such as `3600`, `90m` or `12h` (default: `duration_seconds` of the profile)
+        #[clap(short, long, value_parser = utils::duration::parse_seconds)]
+        duration: Option<i32>,
+
+        /// MFA code for the request (`-` reads stdin, default: prompt or `mfa_process` of the profile)
+        #[clap(long, value_name = "CODE", conflicts_with = "saml_assertion")]
+        token_code: Option<String>,
+
+        #[clap(flatten)]
+        role: Box<RoleArgs>,
+
+        /// Base64 encoded SAML assertion file to get the session with AssumeRoleWithSAML (`-` reads stdin)
+        #[clap(long, value_name = "FILE")]
+        saml_assertion: Option<String>,
+
+        /// Role to assume from the SAML assertion (default: select from the roles in the assertion)
+        #[clap(long, value_name = "ROLE_ARN", requires = "saml_assertion")]
+        saml_role: Option<String>,
     },
     /// Same process as `aws configure`
     Configure {},
@@ -59,13 +104,147 @@ enum CliSubCommand {
     },
     /// List profile from credential
     Ls {},
+    /// Print credentials as JSON for `credential_process`
+    CredentialProcess {
+        /// Profile to be used
+        #[clap(short, long)]
+        profile: Option<String>,
+
+        /// Forces the session token to be updated.
+        #[clap(short, long)]
+        force: bool,
+    },
+    /// Add a profile that uses `credential_process` to the config
+    InstallProcess {
+        /// Profile from which the session token is to be obtained
+        #[clap(short, long)]
+        profile: Option<String>,
+
+        /// Name of the profile to be added (default: `<profile>-process`)
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Manage the encrypted vault for long-term access keys
+    Vault {
+        #[clap(subcommand)]
+        sub: VaultSubCommand,
+    },
+    /// Generate MFA codes from the seed of a virtual MFA device
+    Mfa {
+        #[clap(subcommand)]
+        sub: MfaSubCommand,
+    },
+    /// List backups of the AWS files
+    Backups {},
+    /// Restore an AWS file from a backup
+    Restore {
+        /// Backup ID to restore (see `awsst backups`)
+        backup: Option<String>,
+
+        /// Only show the differences from the current file
+        #[clap(short, long)]
+        diff: bool,
+    },
+}
+
+// `vault`サブコマンドに対する処理
+#[derive(Subcommand, Debug)]
+enum VaultSubCommand {
+    /// Move long-term access keys from credentials into the vault
+    Migrate {},
+}
+
+// `mfa`サブコマンドに対する処理
+#[derive(Subcommand, Debug)]
+enum MfaSubCommand {
+    /// Store the seed of the virtual MFA device in the vault or credential store command
+    Enroll {
+        /// Profile to be used
+        #[clap(short, long)]
+        profile: Option<String>,
+    },
+    /// Remove the stored seed and enter MFA codes at the prompt again
+    Remove {
+        /// Profile to be used
+        #[clap(short, long)]
+        profile: Option<String>,
+    },
+}
+
+// AssumeRoleの任意のパラメータ
+// 指定した場合は`config`のプロファイルの設定より優先する
+#[derive(Args, Debug)]
+struct RoleArgs {
+    /// External ID for AssumeRole (default: `external_id` of the profile)
+    #[clap(long)]
+    external_id: Option<String>,
+
+    /// Source identity for AssumeRole (default: `source_identity` of the profile)
+    #[clap(long)]
+    source_identity: Option<String>,
+
+    /// Session tag such as `Project=app`. Can be given multiple times (default: `tags` of the profile)
+    #[clap(long = "tag", value_parser = sts_client::parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Key of the session tag passed on to chained roles. Can be given multiple times (default: `transitive_tag_keys` of the profile)
+    #[clap(long = "transitive-tag-key")]
+    transitive_tag_keys: Vec<String>,
+
+    /// Inline session policy as JSON or `file://path` (default: `policy` of the profile)
+    #[clap(long, value_parser = sts_client::read_policy)]
+    policy: Option<String>,
+
+    /// ARN of the managed policy used as a session policy. Can be given multiple times (default: `policy_arns` of the profile)
+    #[clap(long = "policy-arn")]
+    policy_arns: Vec<String>,
+}
+
+impl From<RoleArgs> for sts_client::RoleOptions {
+    fn from(args: RoleArgs) -> Self {
+        sts_client::RoleOptions {
+            external_id: args.external_id,
+            source_identity: args.source_identity,
+            tags: args.tags,
+            transitive_tag_keys: args.transitive_tag_keys,
+            policy: args.policy,
+            policy_arns: args.policy_arns,
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     // 引数を取得
     let args = Cli::parse();
 
+    // 失敗した場合はエラーの種類ごとの終了コードで終了する
+    if let Err(e) = run(args).await {
+        let error = error::AppError::classify(e);
+        let mut prompter = utils::prompt::Prompter::new();
+        prompter.error(error.to_string().as_str());
+        std::process::exit(error.exit_code());
+    }
+}
+
+/// 引数に応じた処理を実行
+async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // AWS関連のファイルを配置するディレクトリを設定
+    if let Some(dir) = args.aws_dir {
+        utils::file::set_aws_dir(dir);
+    }
+
+    // ファイルを書き込まずに差分のみ表示する
+    if args.dry_run {
+        utils::file::set_dry_run();
+    }
+
+    // パーミッションの修正のみを行う
+    if args.fix_permissions {
+        profile::fix_permissions()?;
+        return Ok(());
+    }
+
     // サブコマンドが指定されている場合
     if let Some(sub) = args.sub {
         match sub {
@@ -73,10 +252,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // 初期処理
                 profile::initialize()?;
             }
-            CliSubCommand::Session { profile, force } => {
+            CliSubCommand::Session {
+                profile,
+                force,
+                duration,
+                token_code,
+                role,
+                saml_assertion,
+                saml_role,
+            } => match saml_assertion {
+                // SAMLアサーションでセッショントークン取得
+                Some(assertion) => {
+                    profile::saml_session(profile, assertion, saml_role, duration, (*role).into())
+                        .await?;
+                }
                 // セッショントークン取得
-                profile::session_token(profile, force).await?;
-            }
+                None => {
+                    // MFAのコードが指定されている場合は入力させずに利用する
+                    if let Some(code) = token_code {
+                        sts_client::set_token_code(&code)?;
+                    }
+                    profile::session_token(profile, force, duration, (*role).into()).await?
+                }
+            },
             CliSubCommand::Configure {} => {
                 // configureで新たにプロファイルを生成
                 profile::configure().await?
@@ -97,10 +295,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // プロファイル一覧表示
                 profile::list()?;
             }
+            CliSubCommand::CredentialProcess { profile, force } => {
+                // `credential_process`用の認証情報を出力
+                profile::credential_process(profile, force).await?;
+            }
+            CliSubCommand::InstallProcess { profile, name } => {
+                // `credential_process`を利用するプロファイルを追加
+                profile::install_process(profile, name)?;
+            }
+            CliSubCommand::Vault { sub } => match sub {
+                VaultSubCommand::Migrate {} => {
+                    // 長期のアクセスキーを保管庫に移す
+                    profile::vault_migrate()?;
+                }
+            },
+            CliSubCommand::Mfa { sub } => match sub {
+                MfaSubCommand::Enroll { profile } => {
+                    // 仮想MFAデバイスのシードを登録
+                    profile::mfa_enroll(profile)?;
+                }
+                MfaSubCommand::Remove { profile } => {
+                    // 仮想MFAデバイスのシードを削除
+                    profile::mfa_remove(profile)?;
+                }
+            },
+            CliSubCommand::Backups {} => {
+                // バックアップ一覧表示
+                profile::backups()?;
+            }
+            CliSubCommand::Restore { backup, diff } => {
+                // バックアップから復元
+                profile::restore(backup, diff)?;
+            }
         }
     } else {
         // サブコマンドが指定されていない場合はセッショントーク取得を行う
-        profile::session_token(args.profile, args.force).await?;
+        if let Some(code) = args.token_code {
+            sts_client::set_token_code(&code)?;
+        }
+        profile::session_token(args.profile, args.force, args.duration, args.role.into()).await?;
     }
 
     Ok(())
@@ -0,0 +1,140 @@
+use super::configs::{AWSConfigs, Config};
+use super::credentials::{AWSCredentials, Credential};
+use super::select::AWSSelecteds;
+use super::store::profile_name;
+use crate::error::AppError;
+use crate::sts_client::RoleOptions;
+
+/// `source_profile`のセッションを取得
+/// `cred`は本アプリ用のサフィックスつきの情報を指定し、`source_profile`が未設定の場合は`None`
+pub async fn source_session(
+    credentials: &mut AWSCredentials,
+    configs: &AWSConfigs,
+    selecteds: &AWSSelecteds,
+    cred: &Credential,
+) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+    let Some(source) = &cred.source_profile else {
+        return Ok(None);
+    };
+    let mut visited = vec![profile_name(&cred.name).to_string()];
+    let session = resolve(
+        credentials,
+        configs,
+        selecteds,
+        source.clone(),
+        &mut visited,
+    )
+    .await?;
+    Ok(Some(session))
+}
+
+/// 指定のプロファイルのセッションを取得
+/// 期限内のセッションがある場合はそのまま利用し、期限切れの場合は`source_profile`を辿って取得し直す
+/// `visited`には辿ってきたプロファイル名を保持し、循環している場合はエラーとする
+async fn resolve(
+    credentials: &mut AWSCredentials,
+    configs: &AWSConfigs,
+    selecteds: &AWSSelecteds,
+    name: String,
+    visited: &mut Vec<String>,
+) -> Result<Credential, Box<dyn std::error::Error>> {
+    if visited.contains(&name) {
+        return Err(failed(format!(
+            "Oops... source_profile is circular: {} -> {}..",
+            visited.join(" -> "),
+            name
+        )));
+    }
+    visited.push(name.clone());
+
+    // `config`に存在しないプロファイルはリージョンなどを未設定として扱う
+    let config = configs.items.get(&name).cloned().unwrap_or_else(|| Config {
+        name: name.clone(),
+        ..Default::default()
+    });
+    let refresh_before = super::refresh_before(&config, selecteds)?;
+
+    // OIDCトークンを利用するプロファイルは期限切れの場合にAssumeRoleWithWebIdentityで取得し直す
+    if let Some(web_identity) = config.web_identity().map_err(AppError::Validation)? {
+        let session = super::web_identity_session(
+            credentials,
+            &config,
+            &web_identity,
+            false,
+            refresh_before,
+            None,
+            &RoleOptions::default(),
+        )
+        .await?;
+        return Ok(session.unwrap_or_else(|| credentials.use_credential(name)));
+    }
+
+    if !credentials.exists_credential(name.clone()) {
+        return Err(failed(format!(
+            "Oops... source_profile [{}] does not exists in credentials..",
+            name
+        )));
+    }
+    // 期限内の場合は保存されているセッションをそのまま利用する
+    let Some(mut cred) = credentials.auth_credential(name.clone(), false, refresh_before)? else {
+        return Ok(credentials.use_credential(name));
+    };
+
+    // さらに`source_profile`が設定されている場合は先にそのセッションを取得する
+    let source = match cred.source_profile.clone() {
+        Some(source) => {
+            Some(Box::pin(resolve(credentials, configs, selecteds, source, visited)).await?)
+        }
+        None => None,
+    };
+    let result = cred
+        .sts_credential(&config, None, source.as_ref(), &RoleOptions::default())
+        .await?;
+    credentials
+        .set_credential(&config, cred.name.clone(), result)
+        .await
+        .ok_or_else(|| {
+            failed(format!(
+                "Oops... failed update credential of source_profile [{}]..",
+                name
+            ))
+        })
+}
+
+/// エラーメッセージからエラーを生成
+fn failed(msg: String) -> Box<dyn std::error::Error> {
+    Box::new(AppError::Validation(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{document::AWSDocument, AWSFileManager};
+
+    #[tokio::test]
+    async fn source_profile_is_circular() {
+        // 期限が未設定のため期限切れとして扱われ、STSへのリクエストの前に連鎖元を辿る
+        let text = "[a]\n\
+            aws_access_key_id = AKIAA\n\
+            aws_secret_access_key = sa\n\
+            role_arn = arn:aws:iam::123456789012:role/A\n\
+            source_profile = b\n\n\
+            [b]\n\
+            aws_access_key_id = AKIAB\n\
+            aws_secret_access_key = sb\n\
+            role_arn = arn:aws:iam::123456789012:role/B\n\
+            source_profile = a\n";
+        let mut credentials = AWSCredentials::new(AWSDocument::parse(text).unwrap());
+        let configs = AWSConfigs::new(AWSDocument::parse("").unwrap());
+        let selecteds = AWSSelecteds::new(AWSDocument::parse("").unwrap());
+
+        let cred = credentials.original_credential("a").clone();
+        let error = source_session(&mut credentials, &configs, &selecteds, &cred)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Oops... source_profile is circular: a -> b -> a.."
+        );
+    }
+}
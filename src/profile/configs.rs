@@ -1,27 +1,155 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
 
+use crate::error::AppError;
+use crate::sso_client::SsoConfig;
+use crate::sts_client::{self, RoleOptions, StsEndpoint, WebIdentity, WebIdentityToken};
 use crate::utils;
 
-use super::utils::{AWSFile, AWSFileManager};
+use super::utils::{document::AWSDocument, AWSFile, AWSFileManager};
 
 /// AWS Configファイル情報
 pub struct AWSConfigs {
+    // `[default]`と`[profile name]`のプロファイル
     pub items: HashMap<String, Config>,
+    // `[sso-session name]`や`[services name]`などのプロファイル以外のセクション
+    pub sections: Vec<ConfigSection>,
+    // 読み込み時のファイル内容
+    document: AWSDocument,
+}
+
+/// `config`ファイルのセクションの種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    // `[default]`
+    Default,
+    // `[profile name]`
+    Profile,
+    // `[sso-session name]`
+    SsoSession,
+    // `[services name]`
+    Services,
+    // AWS CLIの`[plugins]`など、本ツールで扱わないセクション
+    Other,
+}
+
+impl SectionKind {
+    /// セクション名から種類と名称を取得
+    pub fn parse(section_name: &str) -> (SectionKind, String) {
+        if section_name == "default" {
+            return (SectionKind::Default, section_name.to_string());
+        }
+        let prefixes = [
+            ("profile", SectionKind::Profile),
+            ("sso-session", SectionKind::SsoSession),
+            ("services", SectionKind::Services),
+        ];
+        for (prefix, kind) in prefixes {
+            if let Some((head, name)) = section_name.split_once(char::is_whitespace) {
+                if head == prefix {
+                    return (kind, name.trim().to_string());
+                }
+            }
+        }
+        (SectionKind::Other, section_name.to_string())
+    }
+
+    /// 種類と名称からセクション名を生成
+    pub fn section_name(&self, name: &str) -> String {
+        match self {
+            SectionKind::Default | SectionKind::Other => name.to_string(),
+            SectionKind::Profile => format!("profile {}", name),
+            SectionKind::SsoSession => format!("sso-session {}", name),
+            SectionKind::Services => format!("services {}", name),
+        }
+    }
+}
+
+/// プロファイル以外のセクション
+#[derive(Debug, Clone)]
+pub struct ConfigSection {
+    pub kind: SectionKind,
+    pub name: String,
+    // ネストされたキーは`s3.max_concurrent_requests`の形式で保持する
+    pub values: BTreeMap<String, String>,
 }
 
 /// AWSConfigsの実装
 impl AWSConfigs {
     /// 選択用のリストを生成
     pub fn shape_for_selectable(&self) -> Vec<String> {
-        self.items
-            .iter()
-            .map(|(key, _)| key.clone())
-            .collect::<Vec<String>>()
+        self.items.keys().cloned().collect::<Vec<String>>()
+    }
+
+    /// IAM Identity Center(SSO)のプロファイルの設定を取得
+    /// `sso_session`が設定されている場合は`[sso-session name]`のセクションから開始URLなどを取得する
+    /// SSOのプロファイルでない場合は`None`
+    pub fn sso_config(&self, name: &str) -> Result<Option<SsoConfig>, String> {
+        let Some(config) = self.items.get(name) else {
+            return Ok(None);
+        };
+        let profile = &config.others;
+        if !profile.contains_key(SSO_SESSION_KEY) && !profile.contains_key(SSO_START_URL_KEY) {
+            return Ok(None);
+        }
+
+        // `sso-session`のセクションがある場合はそちらの値を利用する
+        let session_name = profile.get(SSO_SESSION_KEY).cloned();
+        let session = match &session_name {
+            Some(session_name) => {
+                let section = self
+                    .sections
+                    .iter()
+                    .find(|x| x.kind == SectionKind::SsoSession && x.name == *session_name);
+                match section {
+                    Some(section) => Some(&section.values),
+                    None => {
+                        return Err(format!(
+                            "Oops... [sso-session {}] of profile [{}] does not exist..",
+                            session_name, name
+                        ))
+                    }
+                }
+            }
+            None => None,
+        };
+        let value = |key: &str| {
+            session
+                .and_then(|x| x.get(key))
+                .or_else(|| profile.get(key))
+                .filter(|x| !x.is_empty())
+                .cloned()
+        };
+        let required = |key: &str| {
+            value(key).ok_or_else(|| format!("Oops... profile [{}] needs {} for SSO..", name, key))
+        };
+
+        // リフレッシュトークンを利用できるのは`sso_session`を利用する場合のみ
+        let registration_scopes = match value(SSO_REGISTRATION_SCOPES_KEY) {
+            Some(scopes) => scopes
+                .split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect(),
+            None if session_name.is_some() => vec![DEFAULT_SSO_REGISTRATION_SCOPE.to_string()],
+            None => vec![],
+        };
+        Ok(Some(SsoConfig {
+            session_name,
+            start_url: required(SSO_START_URL_KEY)?,
+            region: required(SSO_REGION_KEY)?,
+            registration_scopes,
+            account_id: required(SSO_ACCOUNT_ID_KEY)?,
+            role_name: required(SSO_ROLE_NAME_KEY)?,
+            endpoint_url: value(SSO_ENDPOINT_URL_KEY),
+        }))
     }
 
     /// 指定の名称のconfigが存在するか確認
     pub fn exists_config(&self, name: String) -> bool {
-        self.items.get(&name).is_some()
+        self.items.contains_key(&name)
     }
 
     /// Configのリストから選択または、指定の名称のConfigが存在するかのチェックを行う
@@ -29,20 +157,22 @@ impl AWSConfigs {
         &self,
         profile: Option<String>,
         prompter: &mut utils::prompt::Prompter,
-    ) -> Option<String> {
+    ) -> Result<String, Box<dyn std::error::Error>> {
         let selections = &mut self.shape_for_selectable();
         // 取得したConfigをソート
         selections.sort();
 
-        // 0件の場合はメッセージを表示して終了
+        // 0件の場合はエラー
         if selections.is_empty() {
-            prompter.error("No profile is registered.\nPlease register with the `awsst configure` command before use.");
-            return None;
+            return Err(Box::new(AppError::Validation(
+                "No profile is registered.\nPlease register with the `awsst configure` command before use."
+                    .to_string(),
+            )));
         }
 
         // Configが1件のみの場合はそのまま返却する
         if selections.len() == 1 {
-            return Some(selections[0].clone());
+            return Ok(selections[0].clone());
         }
 
         // プロファイルが指定されている場合
@@ -50,8 +180,10 @@ impl AWSConfigs {
             // 指定のプロファイルが存在するか確認
             if !self.exists_config(_profile.clone()) {
                 // 存在しない場合はエラー
-                prompter.error("Oops.. profile does not exists profile...");
-                return None;
+                return Err(Box::new(AppError::Validation(format!(
+                    "Oops... profile [{}] does not exists..",
+                    _profile
+                ))));
             }
             _profile
         }
@@ -64,44 +196,81 @@ impl AWSConfigs {
             // 選択されたインデックスからConfigの名前を取得
             selections[opt_selection].clone()
         };
-        Some(name)
+        Ok(name)
     }
 }
 
 /// AWSFileの実装を行い, AWSFileとして扱えるようにする
 impl AWSFileManager<Config> for AWSConfigs {
     /// AWSConfigを生成
-    fn new(val: HashMap<String, HashMap<String, String>>) -> AWSConfigs {
+    fn new(document: AWSDocument) -> AWSConfigs {
         let mut items = HashMap::<String, Config>::new();
-        for (key, ele) in val {
+        let mut sections = Vec::<ConfigSection>::new();
+        for (key, mut ele) in document.to_map() {
+            // プロファイル以外のセクションはそのまま保持しておく
+            let (kind, name) = SectionKind::parse(&key);
+            if kind != SectionKind::Default && kind != SectionKind::Profile {
+                sections.push(ConfigSection {
+                    kind,
+                    name,
+                    values: ele.into_iter().collect(),
+                });
+                continue;
+            }
+
+            // `region`と`output`以外のキーはそのまま保持しておく
+            let region = ele.remove("region");
+            let output = ele.remove("output");
             items.insert(
-                key.clone().replace("profile ", ""),
+                name.clone(),
                 Config {
-                    name: key.clone().replace("profile ", ""),
-                    region: ele["region"].clone(),
-                    output: ele["output"].clone(),
+                    name,
+                    region,
+                    output,
+                    others: ele.into_iter().collect(),
                 },
             );
         }
 
-        AWSConfigs { items }
+        AWSConfigs {
+            items,
+            sections,
+            document,
+        }
     }
 
-    /// ファイル出力用にMapを生成
-    fn to_file(&self) -> HashMap<String, HashMap<String, String>> {
-        let mut list = HashMap::<String, HashMap<String, String>>::new();
+    /// ファイル出力用にセクションのリストを生成
+    fn to_file(&self) -> Vec<(String, Vec<(String, String)>)> {
+        let mut list = Vec::<(String, Vec<(String, String)>)>::new();
         for ele in &self.items {
-            list.insert(ele.0.clone(), ele.1.to_file_map());
+            // `default`以外は`profile`をつけたセクション名にする
+            let kind = if ele.0 == "default" {
+                SectionKind::Default
+            } else {
+                SectionKind::Profile
+            };
+            list.push((kind.section_name(ele.0), ele.1.to_file_map()));
         }
+        for section in &self.sections {
+            let values = section
+                .values
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            list.push((section.kind.section_name(&section.name), values));
+        }
+        // 新たに追加するセクションの順序を固定するためにソート
+        list.sort_by(|a, b| a.0.cmp(&b.0));
         list
     }
 
     /// `config`ファイル書き込み
     fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // 書き込み用に整形
-        let data = self.to_file();
+        // 読み込み時のドキュメントに変更内容を反映
+        let mut document = self.document.clone();
+        document.merge(&self.to_file());
         // ファイル書き込み
-        super::utils::file::write(super::CONFIG_FILE_NAME, data, true)
+        super::utils::file::write(super::CONFIG_FILE_NAME, &document, true)
     }
 
     fn add(&mut self, data: Config) {
@@ -114,19 +283,254 @@ impl AWSFileManager<Config> for AWSConfigs {
 }
 
 /// AWS　Configファイル情報のアイテムの構造体
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub name: String,
-    pub region: String,
-    pub output: String,
+    pub region: Option<String>,
+    pub output: Option<String>,
+    // 本ツールで扱わないキー(`role_arn`や`sso_*`など)
+    // ネストされたキーは`s3.max_concurrent_requests`の形式で保持する
+    pub others: BTreeMap<String, String>,
+}
+
+/// セッションの有効期間を指定するキー
+pub const DURATION_KEY: &str = "duration_seconds";
+/// 期限切れとして扱う残り時間を指定するキー
+pub const REFRESH_BEFORE_KEY: &str = "refresh_before";
+/// AssumeRoleのセッション名のテンプレートを指定するキー
+pub const ROLE_SESSION_NAME_KEY: &str = "role_session_name";
+/// SSOの`[sso-session name]`のセクション名を指定するキー
+pub const SSO_SESSION_KEY: &str = "sso_session";
+/// SSOの開始URLを指定するキー
+pub const SSO_START_URL_KEY: &str = "sso_start_url";
+/// SSOのリージョンを指定するキー
+pub const SSO_REGION_KEY: &str = "sso_region";
+/// SSOでクライアントを登録する際のスコープを指定するキー
+pub const SSO_REGISTRATION_SCOPES_KEY: &str = "sso_registration_scopes";
+/// SSOのアカウントIDを指定するキー
+pub const SSO_ACCOUNT_ID_KEY: &str = "sso_account_id";
+/// SSOのロール名を指定するキー
+pub const SSO_ROLE_NAME_KEY: &str = "sso_role_name";
+/// SSOのOIDCとポータルのエンドポイントを変更するキー
+pub const SSO_ENDPOINT_URL_KEY: &str = "sso_endpoint_url";
+/// STSのエンドポイントURLを指定するキー
+pub const ENDPOINT_URL_KEY: &str = "endpoint_url";
+/// FIPSエンドポイントを利用するかを指定するキー
+pub const USE_FIPS_ENDPOINT_KEY: &str = "use_fips_endpoint";
+/// STSのリージョナルエンドポイントを利用するか(`regional`/`legacy`)を指定するキー
+pub const STS_REGIONAL_ENDPOINTS_KEY: &str = "sts_regional_endpoints";
+/// MFAのコードを標準出力に出力するコマンドを指定するキー
+pub const MFA_PROCESS_KEY: &str = "mfa_process";
+/// AssumeRoleWithWebIdentityで引き受けるロールのARNを指定するキー
+pub const ROLE_ARN_KEY: &str = "role_arn";
+/// AssumeRoleWithWebIdentityで利用するOIDCトークンのファイルを指定するキー
+pub const WEB_IDENTITY_TOKEN_FILE_KEY: &str = "web_identity_token_file";
+/// AssumeRoleWithWebIdentityで利用するOIDCトークンを標準出力に出力するコマンドを指定するキー
+pub const WEB_IDENTITY_TOKEN_COMMAND_KEY: &str = "web_identity_token_command";
+/// `sso_registration_scopes`が未設定の場合のスコープ
+const DEFAULT_SSO_REGISTRATION_SCOPE: &str = "sso:account:access";
+/// AssumeRoleの外部IDを指定するキー
+pub const EXTERNAL_ID_KEY: &str = "external_id";
+/// AssumeRoleのソースアイデンティティを指定するキー
+pub const SOURCE_IDENTITY_KEY: &str = "source_identity";
+/// セッションタグを`Key=Value,Key=Value`の形式で指定するキー
+pub const TAGS_KEY: &str = "tags";
+/// ロールの連鎖で引き継ぐセッションタグのキーをカンマ区切りで指定するキー
+pub const TRANSITIVE_TAG_KEYS_KEY: &str = "transitive_tag_keys";
+/// インラインのセッションポリシーを指定するキー(`file://`でファイルを指定できる)
+pub const POLICY_KEY: &str = "policy";
+/// セッションポリシーとする管理ポリシーのARNをカンマ区切りで指定するキー
+pub const POLICY_ARNS_KEY: &str = "policy_arns";
+
+impl Config {
+    /// `configure`で入力された内容から生成
+    pub fn from_configure(profile_name: String, region: String, output: String) -> Self {
+        let mut config = Config {
+            name: profile_name,
+            ..Default::default()
+        };
+        config.set_region_output(region, output);
+        config
+    }
+
+    /// `duration_seconds`に設定されたセッションの有効期間(秒)を取得
+    /// 未設定の場合は`None`
+    pub fn duration_seconds(&self) -> Result<Option<i32>, String> {
+        self.seconds(DURATION_KEY)
+    }
+
+    /// `refresh_before`に設定された期限切れとして扱う残り時間(秒)を取得
+    /// 未設定の場合は`None`
+    pub fn refresh_before(&self) -> Result<Option<i32>, String> {
+        self.seconds(REFRESH_BEFORE_KEY)
+    }
+
+    /// `role_session_name`に設定されたセッション名のテンプレートを取得
+    /// 未設定の場合は`None`
+    pub fn role_session_name(&self) -> Option<&String> {
+        self.others
+            .get(ROLE_SESSION_NAME_KEY)
+            .filter(|x| !x.is_empty())
+    }
+
+    /// `mfa_process`に設定されたMFAのコードを取得するコマンドを取得
+    /// 未設定の場合は`None`
+    pub fn mfa_process(&self) -> Option<&String> {
+        self.others.get(MFA_PROCESS_KEY).filter(|x| !x.is_empty())
+    }
+
+    /// OIDCトークンでロールを引き受けるための設定を取得
+    /// `web_identity_token_file`と`web_identity_token_command`のいずれも未設定の場合は`None`
+    pub fn web_identity(&self) -> Result<Option<WebIdentity>, String> {
+        let value = |key: &str| self.others.get(key).filter(|x| !x.is_empty()).cloned();
+        let token = match (
+            value(WEB_IDENTITY_TOKEN_FILE_KEY),
+            value(WEB_IDENTITY_TOKEN_COMMAND_KEY),
+        ) {
+            (None, None) => return Ok(None),
+            (Some(path), None) => WebIdentityToken::File(path),
+            (None, Some(command)) => WebIdentityToken::Command(command),
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "Oops... profile [{}] has both {} and {}..",
+                    self.name, WEB_IDENTITY_TOKEN_FILE_KEY, WEB_IDENTITY_TOKEN_COMMAND_KEY
+                ))
+            }
+        };
+        let Some(role_arn) = value(ROLE_ARN_KEY) else {
+            return Err(format!(
+                "Oops... profile [{}] needs {} to use the web identity token..",
+                self.name, ROLE_ARN_KEY
+            ));
+        };
+        Ok(Some(WebIdentity { role_arn, token }))
+    }
+
+    /// AssumeRoleの任意のパラメータを取得
+    pub fn role_options(&self) -> Result<RoleOptions, String> {
+        let value = |key: &str| self.others.get(key).filter(|x| !x.is_empty()).cloned();
+        let list = |key: &str| {
+            value(key)
+                .map(|val| {
+                    val.split(',')
+                        .map(|x| x.trim().to_string())
+                        .filter(|x| !x.is_empty())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        };
+        let invalid =
+            |key: &str, e: String| format!("Oops... {} of profile [{}]: {}..", key, self.name, e);
+
+        let tags = list(TAGS_KEY)
+            .iter()
+            .map(|x| sts_client::parse_tag(x))
+            .collect::<Result<Vec<(String, String)>, String>>()
+            .map_err(|e| invalid(TAGS_KEY, e))?;
+        let policy = value(POLICY_KEY)
+            .map(|x| sts_client::read_policy(&x))
+            .transpose()
+            .map_err(|e| invalid(POLICY_KEY, e))?;
+        Ok(RoleOptions {
+            external_id: value(EXTERNAL_ID_KEY),
+            source_identity: value(SOURCE_IDENTITY_KEY),
+            tags,
+            transitive_tag_keys: list(TRANSITIVE_TAG_KEYS_KEY),
+            policy,
+            policy_arns: list(POLICY_ARNS_KEY),
+        })
+    }
+
+    /// STSのエンドポイントの設定を取得
+    pub fn sts_endpoint(&self) -> Result<StsEndpoint, String> {
+        self.resolve_sts_endpoint(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の取得方法を指定してSTSのエンドポイントの設定を取得
+    /// AWS CLIやSDKと同様に環境変数が設定されている場合はプロファイルの設定より優先する
+    fn resolve_sts_endpoint(
+        &self,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<StsEndpoint, String> {
+        let non_empty =
+            |x: Option<String>| x.map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        let value = |key: &str, env_key: &str| {
+            non_empty(env(env_key)).or_else(|| non_empty(self.others.get(key).cloned()))
+        };
+        let invalid = |key: &str, val: &str, expected: &str| {
+            format!(
+                "Oops... {} [{}] of profile [{}] is invalid. Use {}..",
+                key, val, self.name, expected
+            )
+        };
+
+        let endpoint_url = non_empty(env("AWS_ENDPOINT_URL_STS"))
+            .or_else(|| value(ENDPOINT_URL_KEY, "AWS_ENDPOINT_URL"));
+        if let Some(url) = &endpoint_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(invalid(
+                    ENDPOINT_URL_KEY,
+                    url,
+                    "a URL such as `https://...`",
+                ));
+            }
+        }
+        let use_fips = match value(USE_FIPS_ENDPOINT_KEY, "AWS_USE_FIPS_ENDPOINT").as_deref() {
+            None => false,
+            Some(val) if val.eq_ignore_ascii_case("true") => true,
+            Some(val) if val.eq_ignore_ascii_case("false") => false,
+            Some(val) => return Err(invalid(USE_FIPS_ENDPOINT_KEY, val, "`true` or `false`")),
+        };
+        let regional_endpoints = value(STS_REGIONAL_ENDPOINTS_KEY, "AWS_STS_REGIONAL_ENDPOINTS");
+        let legacy = match regional_endpoints.as_deref() {
+            None | Some("regional") => false,
+            Some("legacy") => true,
+            Some(val) => {
+                return Err(invalid(
+                    STS_REGIONAL_ENDPOINTS_KEY,
+                    val,
+                    "`regional` or `legacy`",
+                ))
+            }
+        };
+        Ok(StsEndpoint {
+            endpoint_url,
+            use_fips,
+            legacy,
+        })
+    }
+
+    /// 期間を指定するキーの値を秒数に変換
+    fn seconds(&self, key: &str) -> Result<Option<i32>, String> {
+        let Some(val) = self.others.get(key) else {
+            return Ok(None);
+        };
+        utils::duration::parse_seconds(val)
+            .map(Some)
+            .map_err(|e| format!("Oops... {} of profile [{}]: {}..", key, self.name, e))
+    }
+
+    /// 入力された`region`と`output`を設定する
+    /// 空文字の場合は未設定として扱う
+    pub fn set_region_output(&mut self, region: String, output: String) {
+        self.region = Some(region).filter(|x| !x.is_empty());
+        self.output = Some(output).filter(|x| !x.is_empty());
+    }
 }
 
 impl AWSFile for Config {
     /// ファイルに書き込むための形式に変換
-    fn to_file_map(&self) -> HashMap<String, String> {
-        let mut list = HashMap::<String, String>::new();
-        list.insert("region".to_string(), self.region.clone());
-        list.insert("output".to_string(), self.output.clone());
+    fn to_file_map(&self) -> Vec<(String, String)> {
+        let mut list = Vec::<(String, String)>::new();
+        if let Some(region) = &self.region {
+            list.push(("region".to_string(), region.clone()));
+        }
+        if let Some(output) = &self.output {
+            list.push(("output".to_string(), output.clone()));
+        }
+        for (key, val) in &self.others {
+            list.push((key.clone(), val.clone()));
+        }
         list
     }
 }
@@ -135,8 +539,114 @@ impl Display for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "name: {}, region: {}, output: {}",
+            "name: {}, region: {:?}, output: {:?}",
             self.name, self.region, self.output
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 指定のキーを持つプロファイルを生成
+    fn config(values: &[(&str, &str)]) -> Config {
+        Config {
+            name: "a".to_string(),
+            region: Some("ap-northeast-1".to_string()),
+            others: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// 指定の環境変数のみが設定されている状態で解決する
+    fn resolve(config: &Config, env: &[(&str, &str)]) -> Result<StsEndpoint, String> {
+        let env = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        config.resolve_sts_endpoint(|key| env.get(key).cloned())
+    }
+
+    #[test]
+    fn web_identity_from_config() {
+        assert_eq!(config(&[]).web_identity(), Ok(None));
+        let role_arn = "arn:aws:iam::333333333333:role/ci";
+        assert_eq!(
+            config(&[
+                (ROLE_ARN_KEY, role_arn),
+                (WEB_IDENTITY_TOKEN_FILE_KEY, "~/token")
+            ])
+            .web_identity(),
+            Ok(Some(WebIdentity {
+                role_arn: role_arn.to_string(),
+                token: WebIdentityToken::File("~/token".to_string()),
+            }))
+        );
+        assert!(config(&[(WEB_IDENTITY_TOKEN_COMMAND_KEY, "echo token")])
+            .web_identity()
+            .is_err());
+        assert!(config(&[
+            (ROLE_ARN_KEY, role_arn),
+            (WEB_IDENTITY_TOKEN_FILE_KEY, "~/token"),
+            (WEB_IDENTITY_TOKEN_COMMAND_KEY, "echo token"),
+        ])
+        .web_identity()
+        .is_err());
+    }
+
+    #[test]
+    fn sts_endpoint_defaults() {
+        let endpoint = resolve(&config(&[]), &[]).unwrap();
+        assert_eq!(endpoint.endpoint_url, None);
+        assert!(!endpoint.use_fips);
+        assert!(!endpoint.legacy);
+    }
+
+    #[test]
+    fn sts_endpoint_url_of_env_overrides_profile() {
+        let url = "https://vpce-1.sts.ap-northeast-1.vpce.amazonaws.com";
+        let profile = config(&[(ENDPOINT_URL_KEY, url)]);
+        let env = [
+            ("AWS_ENDPOINT_URL_STS", "http://localhost:1"),
+            ("AWS_ENDPOINT_URL", "http://localhost:2"),
+        ];
+        // サービス固有の環境変数、共通の環境変数、プロファイルの順に優先する
+        let endpoint = resolve(&profile, &env).unwrap();
+        assert_eq!(endpoint.endpoint_url.as_deref(), Some("http://localhost:1"));
+        let endpoint = resolve(&profile, &env[1..]).unwrap();
+        assert_eq!(endpoint.endpoint_url.as_deref(), Some("http://localhost:2"));
+        let endpoint = resolve(&profile, &[]).unwrap();
+        assert_eq!(endpoint.endpoint_url.as_deref(), Some(url));
+    }
+
+    #[test]
+    fn sts_endpoint_fips_and_regional_settings() {
+        let profile = config(&[
+            (USE_FIPS_ENDPOINT_KEY, "TRUE"),
+            (STS_REGIONAL_ENDPOINTS_KEY, "legacy"),
+        ]);
+        let endpoint = resolve(&profile, &[]).unwrap();
+        assert!(endpoint.use_fips);
+        assert!(endpoint.legacy);
+
+        // FIPSと地域エンドポイントの指定は環境変数を優先する
+        let env = [
+            ("AWS_USE_FIPS_ENDPOINT", "false"),
+            ("AWS_STS_REGIONAL_ENDPOINTS", "regional"),
+        ];
+        let endpoint = resolve(&profile, &env).unwrap();
+        assert!(!endpoint.use_fips);
+        assert!(!endpoint.legacy);
+    }
+
+    #[test]
+    fn sts_endpoint_rejects_invalid_values() {
+        assert!(resolve(&config(&[(ENDPOINT_URL_KEY, "localhost:8080")]), &[]).is_err());
+        assert!(resolve(&config(&[(USE_FIPS_ENDPOINT_KEY, "yes")]), &[]).is_err());
+        assert!(resolve(&config(&[(STS_REGIONAL_ENDPOINTS_KEY, "global")]), &[]).is_err());
+    }
+}
@@ -3,7 +3,9 @@ use std::{collections::BTreeMap, error::Error};
 use prettytable::{cell, format, row, Table};
 
 use super::{configs::Config, credentials::Credential};
+use crate::error::AppError;
 
+#[derive(Default)]
 pub struct AWSConfigure {
     pub profile: String,
     pub region: String,
@@ -13,36 +15,15 @@ pub struct AWSConfigure {
     pub mfa_role: Option<String>,
 }
 
-impl Default for AWSConfigure {
-    fn default() -> Self {
-        AWSConfigure {
-            profile: String::from(""),
-            region: String::from(""),
-            output: String::from(""),
-            access_key: String::from(""),
-            secret_access_key: String::from(""),
-            mfa_role: None,
-        }
-    }
-}
-
 impl AWSConfigure {
     pub fn from_conf(config: Config, credential: Option<Credential>) -> Self {
         let _credential = credential.unwrap_or_default();
         AWSConfigure {
             profile: config.name,
-            region: config.region,
-            output: config.output,
-            access_key: if let Some(ak) = _credential.access_key_id {
-                ak
-            } else {
-                String::from("")
-            },
-            secret_access_key: if let Some(sak) = _credential.secret_access_key {
-                sak
-            } else {
-                String::from("")
-            },
+            region: config.region.unwrap_or_default(),
+            output: config.output.unwrap_or_default(),
+            access_key: _credential.access_key_id.unwrap_or_default(),
+            secret_access_key: _credential.secret_access_key.unwrap_or_default(),
             mfa_role: _credential.mfa_serial,
         }
     }
@@ -128,9 +109,9 @@ impl AWSConfigure {
         // 確認フォーム表示
         let result = prompter.confirm_prompt("Is it okay to add with the displayed contents?")?;
 
-        // 登録する場合の処理
+        // 登録しない場合はキャンセルとして扱う
         if !result {
-            return Ok(());
+            return Err(Box::new(AppError::Cancelled));
         }
 
         // 各情報を設定
@@ -1,31 +1,56 @@
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
-use std::{collections::HashMap, env, fmt::Display, time::SystemTime};
-
-use super::utils::{AWSFile, AWSFileManager};
-use crate::sts_client::{aws_sts_request, caller_identity};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fmt::Display,
+    time::SystemTime,
+};
+
+use super::configs::AWSConfigs;
+use super::store::{profile_name, CredentialStore, FileStore, StoreKind, Stores};
+use super::utils::{document::AWSDocument, AWSFile, AWSFileManager};
+use super::vault::Vault;
+use crate::sts_client::{aws_sts_request, caller_identity, RoleOptions};
 
 pub const KEY_SUFFIX: &str = "awsst";
+/// `refresh_before`が未設定の場合に期限切れとして扱う残り時間(秒)
+pub const DEFAULT_REFRESH_BEFORE: i32 = 3 * 3600;
+/// 仮想MFAデバイスのシードを保存するキー
+pub const MFA_TOTP_SEED_KEY: &str = "mfa_totp_seed";
+/// `Credential`の項目として読み書きするキー
+const MANAGED_KEYS: [&str; 10] = [
+    "aws_access_key_id",
+    "aws_secret_access_key",
+    "aws_session_token",
+    "expiration",
+    "mfa_serial",
+    MFA_TOTP_SEED_KEY,
+    "role_arn",
+    "account",
+    "source_profile",
+    "assumed_role",
+];
 
 /// AWS Credentialファイル情報
-#[derive(Debug)]
 pub struct AWSCredentials {
     // 通常のAWS CLIで利用されるサフィックスついていない情報
     pub bases: Vec<Credential>,
     // アクセスキーを保存するアプリで利用するサッフィクスつきの情報
     pub originals: Vec<Credential>,
+    // 読み込み時のファイル内容
+    document: AWSDocument,
+    // サフィックスつきの情報のプロファイルごとの保存先
+    stores: Stores,
+    // 削除したサフィックスつきの情報の名称
+    removed: Vec<String>,
+    // 保存先から長期のアクセスキーを読み込んだプロファイル名
+    loaded: Vec<String>,
 }
 
 impl AWSCredentials {
     // キーとする名称からCredential情報を取得
     fn __credential_by_key(&self, vec: &[Credential], key: &str) -> Option<Credential> {
-        // filter等を利用するとライフタイム的に問題があるため、
-        // forで探索を行う
-        for ele in vec {
-            if ele.name == *key {
-                return Some(ele.clone());
-            }
-        }
-        None
+        vec.iter().find(|ele| ele.name == *key).cloned()
     }
 
     /// 指定のキー名のCredentialが存在するか確認
@@ -41,31 +66,43 @@ impl AWSCredentials {
         self.__credential_by_key(&self.bases, &key).unwrap()
     }
 
+    /// ベースの情報のセッションが期限切れか確認
+    /// 存在しない場合は期限切れとして扱う
+    pub fn is_session_expired(&self, key: &str, refresh_before: i32) -> bool {
+        self.__credential_by_key(&self.bases, key)
+            .is_none_or(|cred| cred.is_expired(refresh_before))
+    }
+
     /// 本アプリ用の認証情報を保存するキーを取得
-    pub fn auth_credential(&mut self, key: String, force: bool) -> Option<Credential> {
-        // サフィックスがついていない情報を取得し、期限の確認を行う.
-        let cred = self.use_credential(key.clone());
-        // 強制更新ではない場合は期限確認を行う
-        if !force {
-            // 期限内であった場合はそのまま返却
-            if !cred.is_expired() {
-                return None;
-            }
+    /// 残り時間が`refresh_before`(秒)以上ある場合は`None`
+    /// 更新が必要な場合のみ長期のアクセスキーを保存先から読み込む
+    pub fn auth_credential(
+        &mut self,
+        key: String,
+        force: bool,
+        refresh_before: i32,
+    ) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+        // 強制更新ではない場合は期限確認を行い、期限内であれば保存先を開かずに返却
+        if !force && !self.is_session_expired(&key, refresh_before) {
+            return Ok(None);
         }
+        self.load(&key)?;
 
         // 期限切れの場合はoriginalの情報を取得
+        Ok(Some(self.original_credential(&key).clone()))
+    }
+
+    /// 本アプリ用に保存しているサフィックスつきの情報を取得
+    /// 存在しない場合はサフィックスがついていない情報から生成してMapに追加しておく
+    pub fn original_credential(&mut self, key: &str) -> &mut Credential {
         let origin_key = format!("{}-{}", key, KEY_SUFFIX);
-        let origin = self.__credential_by_key(&self.originals, &origin_key);
-        if let Some(_origin) = origin {
-            return Some(_origin);
+        if let Some(index) = self.originals.iter().position(|x| x.name == origin_key) {
+            return &mut self.originals[index];
         }
-        // // 存在しない場合は新たに生成してMapに追加しておく
-        let mut new_cred = cred;
-        new_cred.name = format!("{}-{}", key, KEY_SUFFIX);
+        let mut new_cred = self.use_credential(key.to_string());
+        new_cred.name = origin_key;
         self.originals.push(new_cred);
-
-        // // 新たに生成した情報を返却
-        self.__credential_by_key(&self.originals, &origin_key)
+        self.originals.last_mut().unwrap()
     }
 
     /// サフィックスのついた本アプリ用に保存しているCredential情報を優先的に取得
@@ -78,13 +115,7 @@ impl AWSCredentials {
         }
 
         // オリジナルに存在しない場合はベースから取得
-        for ele in self.bases.iter_mut() {
-            if ele.name == key {
-                return Some(ele);
-            }
-        }
-
-        None
+        self.bases.iter_mut().find(|ele| ele.name == key)
     }
 
     /// AWSのクレデンシャル情報を設定
@@ -119,9 +150,14 @@ impl AWSCredentials {
                 // originalに存在しない場合は生成する
                 if !is_exists {
                     let mut cloned = ele.clone();
-                    cloned.name = suf_key;
+                    cloned.name = suf_key.clone();
                     self.originals.push(cloned);
                 }
+                // ロールで取得したセッションは、ロールの連鎖の判定に利用するため記録しておく
+                ele.assumed_role = self
+                    .originals
+                    .iter()
+                    .any(|x| x.name == suf_key && x.role_arn.is_some());
                 ele.update_credential(config, cred).await;
                 return Some(ele.clone());
             }
@@ -129,72 +165,199 @@ impl AWSCredentials {
         None
     }
 
-    /// HashMapから設定
-    fn set_from_map(key: String, ele: &HashMap<String, String>) -> Credential {
-        let role = ele.contains_key("assumed_role") && ele.get("assumed_role").unwrap() == "true";
+    /// SAMLやSSOで取得したセッションを設定
+    /// 長期のアクセスキーを利用しないため、ベースの情報のみ追加、更新する
+    pub async fn set_session(
+        &mut self,
+        config: &super::configs::Config,
+        key: String,
+        cred: aws_sdk_sts::types::Credentials,
+    ) -> Credential {
+        let index = match self.bases.iter().position(|ele| ele.name == key) {
+            Some(index) => index,
+            None => {
+                self.bases.push(Credential {
+                    name: key.clone(),
+                    ..Default::default()
+                });
+                self.bases.len() - 1
+            }
+        };
 
-        Credential {
-            name: key,
-            access_key_id: Self::get_value_from_map(ele, "aws_access_key_id"),
-            secret_access_key: Self::get_value_from_map(ele, "aws_secret_access_key"),
-            session_token: Self::get_value_from_map(ele, "aws_session_token"),
-            expiration: Self::get_value_from_map(ele, "expiration"),
-            mfa_serial: Self::get_value_from_map(ele, "mfa_serial"),
-            role_arn: Self::get_value_from_map(ele, "role_arn"),
-            account: Self::get_value_from_map(ele, "account"),
-            source_profile: Self::get_value_from_map(ele, "source_profile"),
-            assumed_role: role,
+        // 長期のアクセスキーが設定されている場合は上書きしないようサフィックスつきの情報に移す
+        let suf_key = format!("{}-{}", key, KEY_SUFFIX);
+        let base = &self.bases[index];
+        if base.access_key_id.is_some()
+            && base.session_token.is_none()
+            && !self.originals.iter().any(|ele| ele.name == suf_key)
+        {
+            let mut cloned = base.clone();
+            cloned.name = suf_key;
+            self.originals.push(cloned);
         }
+
+        let base = &mut self.bases[index];
+        base.assumed_role = true;
+        base.update_credential(config, cred).await;
+        base.clone()
     }
 
-    /// HashMapからOption型で取り出す
-    fn get_value_from_map(map: &HashMap<String, String>, key: &str) -> Option<String> {
-        map.get(key).cloned()
+    /// 長期のアクセスキーの保存先を設定する
+    /// 保存先からの読み込みは、利用するプロファイルごとに`load`で行う
+    pub fn attach_stores(
+        &mut self,
+        configs: &AWSConfigs,
+        vault: Option<Vault>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stores.configure(configs, vault)
+    }
+
+    /// `credentials`ファイルに長期のアクセスキーを保存しているか
+    pub fn has_long_term_keys(&self, profile: &str) -> bool {
+        let suf_key = format!("{}-{}", profile, KEY_SUFFIX);
+        self.originals.iter().any(|ele| ele.name == suf_key)
+            || self.bases.iter().any(|ele| {
+                ele.name == profile && ele.access_key_id.is_some() && ele.session_token.is_none()
+            })
+    }
+
+    /// プロファイルの長期のアクセスキーの保存先の種類
+    pub fn store_kind(&self, profile: &str) -> StoreKind {
+        self.stores.kind(profile)
+    }
+
+    /// `credentials`ファイル以外に保存しているプロファイルの長期のアクセスキーを読み込む
+    /// `credentials`ファイルに残っている長期のアクセスキーは書き込み時に保存先へ移し、移す場合は`true`を返却
+    pub fn load(&mut self, profile: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.stores.kind(profile) == StoreKind::File
+            || self.loaded.iter().any(|ele| ele == profile)
+        {
+            return Ok(false);
+        }
+        self.stores.open(profile)?;
+        self.loaded.push(profile.to_string());
+
+        // ファイルに保存しているサフィックスつきの情報は書き込み時に保存先へ移す
+        // 保存先にすでに存在する場合は保存先の情報を優先する
+        let suf_key = format!("{}-{}", profile, KEY_SUFFIX);
+        let stored = self.stores.store(profile).get(&suf_key)?;
+        let index = self.originals.iter().position(|ele| ele.name == suf_key);
+        let mut moved = match (index, stored) {
+            (Some(index), Some(cred)) => {
+                self.originals[index] = cred;
+                false
+            }
+            (None, Some(cred)) => {
+                self.originals.push(cred);
+                false
+            }
+            (Some(_), None) => true,
+            (None, None) => false,
+        };
+
+        // セッショントークンを持たないベースの情報は長期のアクセスキーのため保存先に移す
+        if let Some(index) = self.bases.iter().position(|ele| ele.name == profile) {
+            moved |= self.secure_base(index);
+        }
+        Ok(moved)
+    }
+
+    /// ベースの情報が長期のアクセスキーの場合はサフィックスつきの情報に移す
+    /// `credentials`ファイルに保存するプロファイルは対象外
+    /// 移した場合は`true`を返却
+    fn secure_base(&mut self, index: usize) -> bool {
+        let base = &mut self.bases[index];
+        if self.stores.kind(&base.name) == StoreKind::File
+            || base.access_key_id.is_none()
+            || base.session_token.is_some()
+        {
+            return false;
+        }
+
+        let suf_key = format!("{}-{}", base.name, KEY_SUFFIX);
+        if !self.originals.iter().any(|ele| ele.name == suf_key) {
+            let mut cloned = base.clone();
+            cloned.name = suf_key;
+            self.originals.push(cloned);
+        }
+        base.access_key_id = None;
+        base.secret_access_key = None;
+        true
     }
 }
 
 /// AWSFileの実装を行い, AWSFileとして扱えるようにする
 impl AWSFileManager<Credential> for AWSCredentials {
     /// AWSCredentialsを生成
-    fn new(val: HashMap<String, HashMap<String, String>>) -> AWSCredentials {
+    fn new(document: AWSDocument) -> AWSCredentials {
         let mut bases = Vec::<Credential>::new();
         let mut originals = Vec::<Credential>::new();
-        for (key, ele) in val {
+        for (key, ele) in document.to_map() {
             if key.contains(KEY_SUFFIX) {
-                originals.push(Self::set_from_map(key, &ele));
+                originals.push(Credential::from_map(key, &ele));
             } else {
-                bases.push(Self::set_from_map(key, &ele));
+                bases.push(Credential::from_map(key, &ele));
             }
         }
 
-        AWSCredentials { bases, originals }
+        AWSCredentials {
+            bases,
+            stores: Stores::new(FileStore::new(originals.clone())),
+            originals,
+            document,
+            removed: vec![],
+            loaded: vec![],
+        }
     }
 
-    /// ファイル出力用にMapを生成
-    fn to_file(&self) -> HashMap<String, HashMap<String, String>> {
-        let mut list = HashMap::<String, HashMap<String, String>>::new();
+    /// ファイル出力用にセクションのリストを生成
+    fn to_file(&self) -> Vec<(String, Vec<(String, String)>)> {
+        let mut list = Vec::<(String, Vec<(String, String)>)>::new();
 
         // original分のデータを生成
-        for ele in &self.originals {
-            list.insert(ele.name.clone(), ele.to_file_map());
-        }
+        // `credentials`ファイルに保存するもののみ対象とする
+        list.extend(self.stores.file().to_file());
         // base分のデータを生成
         for ele in &self.bases {
-            list.insert(ele.name.clone(), ele.to_file_map());
+            list.push((ele.name.clone(), ele.to_file_map()));
         }
         list
     }
 
     /// `credentials`ファイル書き込み
     fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // 書き込み用に整形
-        let data = self.to_file();
+        // 長期のアクセスキーを先にそれぞれの保存先へ書き込む
+        for ele in &self.originals {
+            let profile = profile_name(&ele.name);
+            // 保存先から読み込んでいないプロファイルは`credentials`ファイルの内容のまま保持する
+            if self.stores.kind(profile) != StoreKind::File
+                && !self.loaded.iter().any(|ele| ele == profile)
+            {
+                continue;
+            }
+            self.stores.store(profile).put(ele)?;
+            // ほかの保存先に移したものは`credentials`ファイルから削除
+            if self.stores.kind(profile) != StoreKind::File {
+                self.stores.file().delete(&ele.name)?;
+            }
+        }
+        for name in &self.removed {
+            self.stores.store(profile_name(name)).delete(name)?;
+            self.stores.file().delete(name)?;
+        }
+        self.stores.flush()?;
+
+        // 読み込み時のドキュメントに変更内容を反映
+        let mut document = self.document.clone();
+        document.merge(&self.to_file());
         // ファイル書き込み
-        super::utils::file::write(super::CREDENTIAL_FILE_NAME, data, false)
+        super::utils::file::write(super::CREDENTIAL_FILE_NAME, &document, true)
     }
 
     fn add(&mut self, data: Credential) {
         self.bases.push(data);
+        // `credentials`ファイル以外に保存する場合はアクセスキーを保存先に移す
+        self.secure_base(self.bases.len() - 1);
     }
 
     fn remove(&mut self, name: String) {
@@ -208,14 +371,16 @@ impl AWSFileManager<Credential> for AWSCredentials {
         let suf_key = format!("{}-{}", name, KEY_SUFFIX);
         for (i, ele) in self.originals.iter_mut().enumerate() {
             if ele.name == suf_key {
-                self.bases.remove(i);
+                self.originals.remove(i);
                 break;
             }
         }
+        // 読み込んでいない保存先の情報も削除されるよう書き込み時に削除する
+        self.removed.push(suf_key);
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Credential {
     pub name: String,
     pub access_key_id: Option<String>,
@@ -223,30 +388,52 @@ pub struct Credential {
     pub session_token: Option<String>,
     pub expiration: Option<String>,
     pub mfa_serial: Option<String>,
+    // MFAのコードを生成する仮想MFAデバイスのシード(Base32)
+    // `credentials`ファイル以外の保存先のサフィックスつきの情報にのみ保存する
+    pub mfa_totp_seed: Option<String>,
     pub role_arn: Option<String>,
     pub account: Option<String>,
     pub source_profile: Option<String>,
     pub assumed_role: bool,
+    // 本ツールで扱わないキー(`region`など)
+    // 書き込み時に削除しないようそのまま保持する
+    pub others: BTreeMap<String, String>,
 }
 
-impl Default for Credential {
-    fn default() -> Self {
+impl Credential {
+    /// HashMapから設定
+    pub fn from_map(key: String, ele: &HashMap<String, String>) -> Credential {
+        let role = ele.contains_key("assumed_role") && ele.get("assumed_role").unwrap() == "true";
+        // `assumed_role = false`のように本ツールが書き込まない値も保持する
+        let others = ele
+            .iter()
+            .filter(|(k, _)| {
+                !MANAGED_KEYS.contains(&k.as_str()) || (k.as_str() == "assumed_role" && !role)
+            })
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
         Credential {
-            name: String::from(""),
-            access_key_id: None,
-            secret_access_key: None,
-            session_token: None,
-            expiration: None,
-            mfa_serial: None,
-            role_arn: None,
-            account: None,
-            source_profile: None,
-            assumed_role: false,
+            name: key,
+            access_key_id: Self::get_value_from_map(ele, "aws_access_key_id"),
+            secret_access_key: Self::get_value_from_map(ele, "aws_secret_access_key"),
+            session_token: Self::get_value_from_map(ele, "aws_session_token"),
+            expiration: Self::get_value_from_map(ele, "expiration"),
+            mfa_serial: Self::get_value_from_map(ele, "mfa_serial"),
+            mfa_totp_seed: Self::get_value_from_map(ele, MFA_TOTP_SEED_KEY),
+            role_arn: Self::get_value_from_map(ele, "role_arn"),
+            account: Self::get_value_from_map(ele, "account"),
+            source_profile: Self::get_value_from_map(ele, "source_profile"),
+            assumed_role: role,
+            others,
         }
     }
-}
 
-impl Credential {
+    /// HashMapからOption型で取り出す
+    fn get_value_from_map(map: &HashMap<String, String>, key: &str) -> Option<String> {
+        map.get(key).cloned()
+    }
+
     pub fn from_configure(
         profile_name: String,
         access_key_id: String,
@@ -260,42 +447,72 @@ impl Credential {
             session_token: None,
             expiration: None,
             mfa_serial: mfa,
+            mfa_totp_seed: None,
             role_arn: None,
             account: None,
             source_profile: None,
             assumed_role: false,
+            others: BTreeMap::new(),
         }
     }
 
     /// 期限切れかチェック
-    /// 期限が切れている場合は`true`, 期限内の場合は`false`
-    pub fn is_expired(&self) -> bool {
-        // 期限が設定されている場合
-        if let Some(expiration) = &self.expiration {
-            let naive_dt = NaiveDateTime::parse_from_str(expiration, "%Y-%m-%d %H:%M:%S")
-                .expect("failed to parse naive datetime");
-
-            // Local timezone を使って DateTime<Local> に変換
-            let date: DateTime<Local> = Local
-                .from_local_datetime(&naive_dt)
-                .earliest()
-                .expect("failed to convert to local datetime");
-            let now = Local::now();
-            // 比較して、期限が切れていない場合は`false`
-            // if date.cmp(&now) == Ordering::Greater {
-            //     return false;
-            // }
-            let duration = date - now;
-            return duration.num_hours() < 3;
+    /// 残り時間が`refresh_before`(秒)未満の場合は`true`, それ以外は`false`
+    /// 期限の形式が不正な場合は再取得させるために期限切れとして扱う
+    pub fn is_expired(&self, refresh_before: i32) -> bool {
+        match self.remaining_seconds() {
+            Some(remaining) => remaining < i64::from(refresh_before),
+            None => true,
         }
-        true
+    }
+
+    /// 期限までの残り時間(秒)
+    /// 期限が未設定または形式が不正な場合は`None`
+    pub fn remaining_seconds(&self) -> Option<i64> {
+        let date = self.expiration_datetime()?;
+        Some((date - Local::now()).num_seconds())
+    }
+
+    /// 期限を日時に変換
+    /// 未設定または形式が不正な場合は`None`
+    pub fn expiration_datetime(&self) -> Option<DateTime<Local>> {
+        let expiration = self.expiration.as_ref()?;
+        let naive_dt = NaiveDateTime::parse_from_str(expiration, "%Y-%m-%d %H:%M:%S").ok()?;
+        // Local timezone を使って DateTime<Local> に変換
+        Local.from_local_datetime(&naive_dt).earliest()
+    }
+
+    /// `credential_process`で出力するJSONに変換
+    /// 一時的な認証情報でない場合は`None`
+    pub fn to_process_json(&self) -> Option<String> {
+        let (Some(access_key_id), Some(secret_access_key), Some(session_token), Some(expiration)) = (
+            &self.access_key_id,
+            &self.secret_access_key,
+            &self.session_token,
+            self.expiration_datetime(),
+        ) else {
+            return None;
+        };
+
+        let json = serde_json::json!({
+            "Version": 1,
+            "AccessKeyId": access_key_id,
+            "SecretAccessKey": secret_access_key,
+            "SessionToken": session_token,
+            "Expiration": expiration
+                .with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        });
+        Some(json.to_string())
     }
 
     /// 環境変数に利用するAWSプロファイル情報を設定する
     /// 環境変数に設定することで, 本ツール内でAWSリクエストを行う際に利用できるようにする
     pub fn set_environment(&self, config: &super::configs::Config) {
         // リージョンを設定
-        env::set_var("AWS_DEFAULT_REGION", &config.region);
+        if let Some(region) = &config.region {
+            env::set_var("AWS_DEFAULT_REGION", region);
+        }
         // アクセスキーを設定
         if let Some(access_key) = &self.access_key_id {
             env::set_var("AWS_ACCESS_KEY_ID", access_key);
@@ -307,15 +524,20 @@ impl Credential {
     }
 
     /// AWS STSで認証情報を取得
+    /// `duration`が未指定の場合はプロファイルの設定を利用する
+    /// `source`には`source_profile`のセッション、`options`にはプロファイルの設定より優先するAssumeRoleのパラメータを指定する
     pub async fn sts_credential(
         &mut self,
         config: &super::configs::Config,
-    ) -> Result<aws_sdk_sts::types::Credentials, aws_sdk_sts::Error> {
+        duration: Option<i32>,
+        source: Option<&Credential>,
+        options: &RoleOptions,
+    ) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
         // 環境情報を設定
         self.set_environment(config);
 
         // 認証情報取得リクエスト
-        aws_sts_request(config, self.clone()).await
+        aws_sts_request(config, self.clone(), duration, source, options).await
     }
 
     /// クレデンシャル情報更新
@@ -324,53 +546,58 @@ impl Credential {
         config: &super::configs::Config,
         aws_cred: aws_sdk_sts::types::Credentials,
     ) {
+        // アカウント情報を取得して設定しておく
+        let result = caller_identity(config, &aws_cred).await;
+        if let Ok(account) = result {
+            self.account = Some(account);
+        }
+
         self.access_key_id = Some(aws_cred.access_key_id);
         self.secret_access_key = Some(aws_cred.secret_access_key);
         self.session_token = Some(aws_cred.session_token);
 
-        match SystemTime::try_from(aws_cred.expiration) {
-            Ok(dtime) => {
-                // When writing the deadline, do it in local time
-                let datetime: DateTime<Local> = dtime.into();
-                self.expiration = Some(datetime.format("%Y-%m-%d %H:%M:%S").to_string());
-            }
-            Err(_) => return,
-        }
-
-        // アカウント情報を取得して設定しておく
-        let result = caller_identity(config).await;
-        if let Ok(account) = result {
-            self.account = Some(account);
+        if let Ok(dtime) = SystemTime::try_from(aws_cred.expiration) {
+            // When writing the deadline, do it in local time
+            let datetime: DateTime<Local> = dtime.into();
+            self.expiration = Some(datetime.format("%Y-%m-%d %H:%M:%S").to_string());
         }
     }
 
     /// リストに追加するかの判定を行う
     fn __to_file_list_push(
         &self,
-        list: &mut HashMap<String, String>,
+        list: &mut Vec<(String, String)>,
         key: &str,
         data: &Option<String>,
     ) {
         if let Some(val) = data {
-            list.insert(key.to_string(), val.clone());
+            list.push((key.to_string(), val.clone()));
         }
     }
 }
 
 impl AWSFile for Credential {
     /// ファイルに書き込むための形式に変換
-    fn to_file_map(&self) -> HashMap<String, String> {
-        let mut list = HashMap::<String, String>::new();
+    fn to_file_map(&self) -> Vec<(String, String)> {
+        let mut list = Vec::<(String, String)>::new();
         self.__to_file_list_push(&mut list, "aws_access_key_id", &self.access_key_id);
         self.__to_file_list_push(&mut list, "aws_secret_access_key", &self.secret_access_key);
         self.__to_file_list_push(&mut list, "aws_session_token", &self.session_token);
         self.__to_file_list_push(&mut list, "expiration", &self.expiration);
         self.__to_file_list_push(&mut list, "mfa_serial", &self.mfa_serial);
+        self.__to_file_list_push(&mut list, MFA_TOTP_SEED_KEY, &self.mfa_totp_seed);
         self.__to_file_list_push(&mut list, "role_arn", &self.role_arn);
         self.__to_file_list_push(&mut list, "account", &self.account);
         self.__to_file_list_push(&mut list, "source_profile", &self.source_profile);
         if self.assumed_role {
-            list.insert("assumed_role".to_string(), self.assumed_role.to_string());
+            list.push(("assumed_role".to_string(), self.assumed_role.to_string()));
+        }
+        for (key, val) in &self.others {
+            // ロールで取得したセッションに更新した場合は保持していた値より優先する
+            if key == "assumed_role" && self.assumed_role {
+                continue;
+            }
+            list.push((key.clone(), val.clone()));
         }
         list
     }
@@ -385,3 +612,47 @@ impl Display for Credential {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_keeps_unknown_keys_of_other_sections() {
+        let text = "[a]\naws_access_key_id = AKIAA\nregion = ap-northeast-1\nassumed_role = false\n\n# keys of b\n[b]\naws_access_key_id = AKIAB\n";
+        let mut credentials = AWSCredentials::new(AWSDocument::parse(text).unwrap());
+        credentials.remove("b".to_string());
+
+        let mut document = credentials.document.clone();
+        document.merge(&credentials.to_file());
+        assert_eq!(
+            document.to_string(),
+            "[a]\naws_access_key_id = AKIAA\nregion = ap-northeast-1\nassumed_role = false\n"
+        );
+    }
+
+    #[test]
+    fn assumed_role_overrides_kept_value() {
+        let values = HashMap::from([
+            ("assumed_role".to_string(), "false".to_string()),
+            ("region".to_string(), "us-east-1".to_string()),
+        ]);
+        let mut cred = Credential::from_map("a".to_string(), &values);
+        assert_eq!(
+            cred.to_file_map(),
+            vec![
+                ("assumed_role".to_string(), "false".to_string()),
+                ("region".to_string(), "us-east-1".to_string()),
+            ]
+        );
+
+        cred.assumed_role = true;
+        assert_eq!(
+            cred.to_file_map(),
+            vec![
+                ("assumed_role".to_string(), "true".to_string()),
+                ("region".to_string(), "us-east-1".to_string()),
+            ]
+        );
+    }
+}
@@ -1,17 +1,24 @@
 use self::configs::{AWSConfigs, Config};
 use self::credentials::{AWSCredentials, Credential};
 use self::select::Selected;
-use crate::profile::select::new_selected;
+use self::store::CredentialStore;
+use self::vault::Vault;
+use crate::error::AppError;
 use crate::profile::select::AWSSelecteds;
+use crate::sso_client;
+use crate::sts_client::{self, RoleOptions};
 use crate::utils;
 use crate::utils::AWSFileManager;
 use prettytable::{cell, format, row, Table};
 use setenv::get_shell;
-use std::process::exit;
+pub mod chain;
 pub mod configs;
 pub mod configure;
 pub mod credentials;
+pub mod saml;
 pub mod select;
+pub mod store;
+pub mod vault;
 
 pub const CONFIG_FILE_NAME: &str = "config";
 pub const CREDENTIAL_FILE_NAME: &str = "credentials";
@@ -22,7 +29,7 @@ pub const TOOL_FILE_NAME: &str = "awsst";
 pub fn initialize() -> Result<(), Box<dyn std::error::Error>> {
     let mut prompter = utils::prompt::Prompter::new();
     // 現在のプロファイルを取得
-    let selected = read_tool(&mut prompter);
+    let selected = read_tool(&mut prompter)?;
     let profile = selected.items.get("selected");
 
     if let Some(p) = profile {
@@ -39,83 +46,469 @@ pub fn initialize() -> Result<(), Box<dyn std::error::Error>> {
 
 /// セッショントークンを取得する
 ///
+/// `options`はプロファイルの設定より優先するAssumeRoleのパラメータ
 pub async fn session_token(
     profile: Option<String>,
     force: bool,
+    duration: Option<i32>,
+    options: RoleOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut prompter = utils::prompt::Prompter::new();
     // Ctrl+Cのハンドラーを登録
     prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
 
     // configファイル読み込み
-    let configs = read_config(&mut prompter);
+    let configs = read_config(&mut prompter)?;
 
     // 対象のConfig名
-    let selection = configs.selection_config_name(profile, &mut prompter);
-    if selection.is_none() {
-        return Ok(());
-    }
-    let name = selection.unwrap();
+    let name = configs.selection_config_name(profile, &mut prompter)?;
     let config = configs.items.get(&name).unwrap();
 
     // credentialsファイル読み込み
-    let mut credentials = read_credential(&mut prompter);
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
+
+    // 期限切れとして扱う残り時間を取得
+    let selecteds = read_tool(&mut prompter)?;
+    let refresh_before = refresh_before(config, &selecteds)?;
+
+    // SSOのプロファイルの場合はIAM Identity Centerから取得する
+    if let Some(sso) = configs.sso_config(&name).map_err(AppError::Validation)? {
+        // 有効期間や権限はアクセス権限セットで決まるため、指定されたパラメータは無視せずに通知する
+        if duration.is_some() || !options.is_empty() {
+            return Err(Box::new(AppError::Validation(format!(
+                "Oops... --duration and AssumeRole options cannot be used with SSO profile [{}]..",
+                name
+            ))));
+        }
+        let session = sso_session(
+            &mut prompter,
+            &mut credentials,
+            config,
+            sso,
+            force,
+            refresh_before,
+        )
+        .await?;
+        let Some(new_cred) = session else {
+            return skip_renewal(&mut prompter, name, refresh_before);
+        };
+        credentials.write()?;
+        print_expiration(&mut prompter, new_cred.expiration.as_deref());
+        return use_profile(Some(name));
+    }
+
+    // OIDCトークンを利用するプロファイルの場合はAssumeRoleWithWebIdentityで取得する
+    if let Some(web_identity) = config.web_identity().map_err(AppError::Validation)? {
+        let session = web_identity_session(
+            &mut credentials,
+            config,
+            &web_identity,
+            force,
+            refresh_before,
+            duration,
+            &options,
+        )
+        .await?;
+        let Some(new_cred) = session else {
+            return skip_renewal(&mut prompter, name, refresh_before);
+        };
+        credentials.write()?;
+        print_expiration(&mut prompter, new_cred.expiration.as_deref());
+        return use_profile(Some(name));
+    }
+
     // 指定された`config`の名称の`credential`が存在するか確認
     if !credentials.exists_credential(name.clone()) {
-        prompter.error("Oops... does not exists credential..");
-        return Ok(());
+        return Err(missing_credential(&name));
     }
-
     // Credentialを取得
-    let opt_cred = credentials.auth_credential(name.clone(), force);
+    let opt_cred = credentials.auth_credential(name.clone(), force, refresh_before)?;
     // Noneが返却された場合は期限内であるため、スキップ
-    if opt_cred.is_none() {
-        // 取得したセッショントークンのプロファイルを選択状態にする
-        use_profile(Some(name))?;
+    let Some(mut cred) = opt_cred else {
+        return skip_renewal(&mut prompter, name, refresh_before);
+    };
 
-        // メッセージを出力して終了
-        prompter.standard("The credential has more than 3 hours remaining to expire.");
-        prompter.standard("For extensions, please force renewal with the [-f] option");
-        return Ok(());
-    }
-    let mut cred = opt_cred.unwrap();
+    // `source_profile`が設定されている場合は連鎖元のセッションを取得
+    let source = chain::source_session(&mut credentials, &configs, &selecteds, &cred).await?;
 
     // AWS Credentialを取得し、Config情報を更新
-    let result = cred.sts_credential(config).await?;
-    let new_cred = credentials
+    let result = cred
+        .sts_credential(config, duration, source.as_ref(), &options)
+        .await?;
+    // 更新後のCredentialが存在しない場合は失敗した可能性があるため、ここで終了
+    let Some(new_cred) = credentials
         .set_credential(config, cred.name.clone(), result)
-        .await;
+        .await
+    else {
+        return Err(failed_update(&name));
+    };
 
-    // 更新後のCredentialが存在しない場合は失敗した可能性があるため、ここで終了
-    if new_cred.is_none() {
-        prompter.error("Oops... failed update credential..");
-        return Ok(());
-    }
+    // ファイル書き込み
+    credentials.write()?;
 
     // 期限が設定されていたら最後に出力する
-    if let Some(expired) = new_cred.unwrap().expiration {
-        prompter.keyvalue("Success! Token expiration is ", expired.as_str());
+    print_expiration(&mut prompter, new_cred.expiration.as_deref());
+
+    // 取得したセッショントークンのプロファイルを選択状態にする
+    use_profile(Some(name))?;
+
+    Ok(())
+}
+
+/// IAM Identity Center(SSO)でセッショントークンを取得し、`credentials`のベースの情報を更新する
+/// 保存しているセッションが期限内で強制更新ではない場合は取得せずに`None`を返却
+async fn sso_session(
+    prompter: &mut utils::prompt::Prompter,
+    credentials: &mut AWSCredentials,
+    config: &Config,
+    sso: sso_client::SsoConfig,
+    force: bool,
+    refresh_before: i32,
+) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+    if !force && !credentials.is_session_expired(&config.name, refresh_before) {
+        return Ok(None);
     }
 
+    // AWS Credentialを取得し、Credential情報を更新
+    let result = sso_client::sso_role_credentials(&sso, prompter).await?;
+    // リージョン未設定のプロファイルはアカウント情報の取得に`sso_region`を利用する
+    let mut config = config.clone();
+    config.region.get_or_insert(sso.region);
+    Ok(Some(
+        credentials
+            .set_session(&config, config.name.clone(), result)
+            .await,
+    ))
+}
+
+/// 期限内のため取得し直さずにプロファイルを選択状態にする
+fn skip_renewal(
+    prompter: &mut utils::prompt::Prompter,
+    name: String,
+    refresh_before: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 保存しているセッショントークンのプロファイルを選択状態にする
+    use_profile(Some(name))?;
+
+    // メッセージを出力して終了
+    let msg = format!(
+        "The credential has more than {} remaining to expire.",
+        utils::duration::format_seconds(refresh_before.into())
+    );
+    prompter.standard(msg.as_str());
+    prompter.standard("For extensions, please force renewal with the [-f] option");
+    Ok(())
+}
+
+/// OIDCトークンでセッショントークンを取得し、`credentials`のベースの情報を更新する
+/// 保存しているセッションが期限内で強制更新ではない場合は取得せずに`None`を返却
+async fn web_identity_session(
+    credentials: &mut AWSCredentials,
+    config: &Config,
+    web_identity: &sts_client::WebIdentity,
+    force: bool,
+    refresh_before: i32,
+    duration: Option<i32>,
+    options: &RoleOptions,
+) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+    if !force && !credentials.is_session_expired(&config.name, refresh_before) {
+        return Ok(None);
+    }
+    let result =
+        sts_client::sts_assume_role_with_web_identity(config, web_identity, duration, options)
+            .await?;
+    Ok(Some(
+        credentials
+            .set_session(config, config.name.clone(), result)
+            .await,
+    ))
+}
+
+/// SAMLアサーションでセッショントークンを取得する
+/// `assertion`はbase64でエンコードされたアサーションのファイルで、`-`の場合は標準入力から読み込む
+/// `role`が未指定でアサーションに複数のロールが含まれる場合は選択してもらう
+pub async fn saml_session(
+    profile: Option<String>,
+    assertion: String,
+    role: Option<String>,
+    duration: Option<i32>,
+    options: RoleOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // configファイル読み込み
+    let configs = read_config(&mut prompter)?;
+    // 対象のConfig名
+    let name = configs.selection_config_name(profile, &mut prompter)?;
+    let config = configs.items.get(&name).unwrap();
+
+    // アサーションから引き受けられるロールを取得
+    let assertion = saml::read_assertion(&assertion)?;
+    let roles = saml::roles(&assertion)?;
+
+    // 引き受けるロールを決定
+    let index = match role {
+        Some(role) => match roles.iter().position(|x| x.role_arn == role) {
+            Some(index) => index,
+            None => {
+                return Err(Box::new(AppError::Validation(format!(
+                    "Oops... the role [{}] is not included in the SAML assertion..",
+                    role
+                ))));
+            }
+        },
+        None if roles.len() == 1 => 0,
+        None => {
+            let selections = roles
+                .iter()
+                .map(|x| x.role_arn.clone())
+                .collect::<Vec<String>>();
+            prompter.select_prompt(&selections, "Please select the role you want to assume")?
+        }
+    };
+    let saml_role = roles[index].clone();
+
+    // credentialsファイル読み込み
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
+    // AWS Credentialを取得し、Credential情報を更新
+    let result = sts_client::sts_assume_role_with_saml(
+        config,
+        saml_role.role_arn,
+        saml_role.principal_arn,
+        assertion,
+        duration,
+        &options,
+    )
+    .await?;
+    let new_cred = credentials.set_session(config, name.clone(), result).await;
+
     // ファイル書き込み
     credentials.write()?;
 
+    // 期限が設定されていたら最後に出力する
+    print_expiration(&mut prompter, new_cred.expiration.as_deref());
+
     // 取得したセッショントークンのプロファイルを選択状態にする
     use_profile(Some(name))?;
 
     Ok(())
 }
 
+/// `credential_process`用に認証情報をJSONで標準出力に出力する
+/// 期限が近い場合はセッショントークンを取得し直してから出力する
+pub async fn credential_process(
+    profile: Option<String>,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+
+    // 標準出力はSDKが読み込むため、JSONのみを出力する
+    // SDKから実行されるため、失敗した場合は終了コードで通知する
+    let json = process_json(&mut prompter, profile, force).await?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+/// `credential_process`で出力するJSONを生成
+async fn process_json(
+    prompter: &mut utils::prompt::Prompter,
+    profile: Option<String>,
+    force: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(prompter)?;
+
+    // configファイル読み込み
+    let configs = read_config(prompter)?;
+    // 対象のConfig名
+    let name = configs.selection_config_name(profile, prompter)?;
+    let config = configs.items.get(&name).unwrap();
+
+    // credentialsファイル読み込み
+    let mut credentials = read_credential_with_store(prompter, &configs)?;
+
+    // 期限切れとして扱う残り時間を取得
+    let selecteds = read_tool(prompter)?;
+    let refresh_before = refresh_before(config, &selecteds)?;
+
+    // SSOのプロファイルの場合はIAM Identity Centerから取得する
+    if let Some(sso) = configs.sso_config(&name).map_err(AppError::Validation)? {
+        let session = sso_session(
+            prompter,
+            &mut credentials,
+            config,
+            sso,
+            force,
+            refresh_before,
+        )
+        .await?;
+        let cred = match session {
+            None => credentials.use_credential(name),
+            Some(new_cred) => {
+                credentials.write()?;
+                new_cred
+            }
+        };
+        return process_json_of(&cred);
+    }
+
+    // OIDCトークンを利用するプロファイルの場合はAssumeRoleWithWebIdentityで取得する
+    if let Some(web_identity) = config.web_identity().map_err(AppError::Validation)? {
+        let session = web_identity_session(
+            &mut credentials,
+            config,
+            &web_identity,
+            force,
+            refresh_before,
+            None,
+            &RoleOptions::default(),
+        )
+        .await?;
+        let cred = match session {
+            None => credentials.use_credential(name),
+            Some(new_cred) => {
+                credentials.write()?;
+                new_cred
+            }
+        };
+        return process_json_of(&cred);
+    }
+
+    // 指定された`config`の名称の`credential`が存在するか確認
+    if !credentials.exists_credential(name.clone()) {
+        return Err(missing_credential(&name));
+    }
+    // 期限内の場合はファイルに保存されているセッショントークンをそのまま利用する
+    let cred = match credentials.auth_credential(name.clone(), force, refresh_before)? {
+        None => credentials.use_credential(name),
+        Some(mut cred) => {
+            // `source_profile`が設定されている場合は連鎖元のセッションを取得
+            let source =
+                chain::source_session(&mut credentials, &configs, &selecteds, &cred).await?;
+            // AWS Credentialを取得し、Credential情報を更新
+            let result = cred
+                .sts_credential(config, None, source.as_ref(), &RoleOptions::default())
+                .await?;
+            let Some(new_cred) = credentials
+                .set_credential(config, cred.name.clone(), result)
+                .await
+            else {
+                return Err(failed_update(&name));
+            };
+            // ファイル書き込み
+            credentials.write()?;
+            new_cred
+        }
+    };
+
+    process_json_of(&cred)
+}
+
+/// セッショントークンを`credential_process`で出力するJSONに変換
+fn process_json_of(cred: &Credential) -> Result<String, Box<dyn std::error::Error>> {
+    cred.to_process_json().ok_or_else(|| {
+        Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] has no session token..",
+            cred.name
+        )))
+        .into()
+    })
+}
+
+/// `credential_process`で本ツールを呼び出すプロファイルを`config`に追加する
+/// 既存のプロファイルの`credentials`の情報が優先されないよう、別名のプロファイルとして追加する
+pub fn install_process(
+    profile: Option<String>,
+    process_profile: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // configファイル読み込み
+    let mut configs = read_config(&mut prompter)?;
+    // 対象のConfig名
+    let name = configs.selection_config_name(profile, &mut prompter)?;
+    let process_name = process_profile.unwrap_or_else(|| format!("{}-process", name));
+    if process_name == name {
+        return Err(Box::new(AppError::Validation(
+            "Oops... the process profile must differ from the source profile..".to_string(),
+        )));
+    }
+
+    // 実行中のバイナリのパスで呼び出すコマンドを生成
+    let mut command = vec![
+        quote_arg(&std::env::current_exe()?.display().to_string()),
+        "credential-process".to_string(),
+        "--profile".to_string(),
+        quote_arg(&name),
+    ];
+    if let Some(dir) = utils::file::custom_aws_dir() {
+        command.push("--aws-dir".to_string());
+        command.push(quote_arg(&dir.display().to_string()));
+    }
+
+    // 元のプロファイルのリージョンと出力形式を引き継ぐ
+    let source = configs.items.get(&name).unwrap();
+    let mut config = configs
+        .items
+        .get(&process_name)
+        .cloned()
+        .unwrap_or_else(|| Config {
+            name: process_name.clone(),
+            region: source.region.clone(),
+            output: source.output.clone(),
+            ..Default::default()
+        });
+    config
+        .others
+        .insert("credential_process".to_string(), command.join(" "));
+    configs.add(config);
+
+    // ファイル書き込みを行う
+    configs.write()?;
+
+    print_complete(
+        &mut prompter,
+        "complete! added profile",
+        Some(&process_name),
+    );
+
+    Ok(())
+}
+
+/// 空白などを含む引数をダブルクォートで囲む
+fn quote_arg(arg: &str) -> String {
+    if arg.chars().any(|c| c.is_whitespace() || c == '"') {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
 /// configureでconfig情報を設定する
 pub async fn configure() -> Result<(), Box<dyn std::error::Error>> {
     let mut prompter = utils::prompt::Prompter::new();
     // Ctrl+Cのハンドラーを登録
     prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
 
     // configファイル読み込み
-    let mut configs = read_config(&mut prompter);
+    let mut configs = read_config(&mut prompter)?;
     // credentialsファイル読み込み
-    let mut credentials = read_credential(&mut prompter);
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
 
     // 情報の入力をさせるためのダイアログを表示
     let mut aws_configure = configure::AWSConfigure::default();
@@ -123,16 +516,18 @@ pub async fn configure() -> Result<(), Box<dyn std::error::Error>> {
 
     // 同一名のconfigが存在するか確認し、存在した場合はエラーを表示して終了
     if configs.exists_config(aws_configure.profile.clone()) {
-        prompter.error("already profile name");
-        return Ok(());
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] already exists..",
+            aws_configure.profile
+        ))));
     }
 
     // 新たなconfig情報を生成
-    configs.add(Config {
-        name: aws_configure.profile.clone(),
-        region: aws_configure.region.clone(),
-        output: aws_configure.output.clone(),
-    });
+    configs.add(Config::from_configure(
+        aws_configure.profile.clone(),
+        aws_configure.region.clone(),
+        aws_configure.output.clone(),
+    ));
 
     // 新たなcredential情報を生成
     credentials.add(Credential::from_configure(
@@ -157,24 +552,23 @@ pub async fn update(profile: Option<String>) -> Result<(), Box<dyn std::error::E
     let mut prompter = utils::prompt::Prompter::new();
     // Ctrl+Cのハンドラーを登録
     prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
 
     // configファイル読み込み
-    let mut configs = read_config(&mut prompter);
+    let mut configs = read_config(&mut prompter)?;
     // credentialsファイル読み込み
-    let mut credentials = read_credential(&mut prompter);
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
 
     // 対象のConfig名を取得
-    let selection = configs.selection_config_name(profile, &mut prompter);
-    if selection.is_none() {
-        return Ok(());
-    }
-    let name = selection.unwrap();
+    let name = configs.selection_config_name(profile, &mut prompter)?;
     let config = configs.items.get_mut(&name).unwrap();
     // 指定された`config`の名称の`credential`が存在するか確認
     if !credentials.exists_credential(name.clone()) {
-        prompter.error("Oops... does not exists credential..");
-        return Ok(());
+        return Err(missing_credential(&name));
     }
+    // 長期のアクセスキーを保存先から読み込む
+    credentials.load(&name)?;
 
     // Credentialを取得
     let opt_cred = credentials.suffix_credential(name.clone());
@@ -185,8 +579,7 @@ pub async fn update(profile: Option<String>) -> Result<(), Box<dyn std::error::E
     aws_configure.dialog_for_user(&mut prompter)?;
 
     // データを上書き
-    config.region = aws_configure.region;
-    config.output = aws_configure.output;
+    config.set_region_output(aws_configure.region, aws_configure.output);
     cred.access_key_id = Some(aws_configure.access_key);
     cred.secret_access_key = Some(aws_configure.secret_access_key);
     cred.mfa_serial = aws_configure.mfa_role;
@@ -203,19 +596,19 @@ pub async fn remove(profile: Option<String>) -> Result<(), Box<dyn std::error::E
     let mut prompter = utils::prompt::Prompter::new();
     // Ctrl+Cのハンドラーを登録
     prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
 
     // configファイル読み込み
-    let mut configs = read_config(&mut prompter);
+    let mut configs = read_config(&mut prompter)?;
     // credentialsファイル読み込み
-    let mut credentials = read_credential(&mut prompter);
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
 
     // 対象のConfig名
-    let selection = configs.selection_config_name(profile, &mut prompter);
-    if selection.is_none() {
-        return Ok(());
-    }
-    let name = selection.unwrap();
+    let name = configs.selection_config_name(profile, &mut prompter)?;
 
+    // 保存先の長期のアクセスキーも削除するため、保存先を読み込んでおく
+    credentials.load(&name)?;
     // ConfigとCredentialを削除
     configs.remove(name.clone());
     credentials.remove(name);
@@ -224,7 +617,7 @@ pub async fn remove(profile: Option<String>) -> Result<(), Box<dyn std::error::E
     configs.write()?;
     credentials.write()?;
 
-    prompter.standard("complete! deleted profile.");
+    print_complete(&mut prompter, "complete! deleted profile.", None);
 
     Ok(())
 }
@@ -236,24 +629,35 @@ pub fn use_profile(profile: Option<String>) -> Result<(), Box<dyn std::error::Er
     prompter.flush();
 
     // configファイル読み込み
-    let configs = read_config(&mut prompter);
+    let configs = read_config(&mut prompter)?;
 
     // 対象のConfig名
-    let selection = configs.selection_config_name(profile, &mut prompter);
-    if selection.is_none() {
-        return Ok(());
-    }
-    let name = selection.unwrap();
+    let name = configs.selection_config_name(profile, &mut prompter)?;
 
     // プロファイル情報を設定
     let config = configs.items.get(&name).unwrap();
     _set_tool_file(config)?;
 
+    // `--dry-run`の場合は環境変数も変更しない
+    if utils::file::is_dry_run() {
+        return Ok(());
+    }
+
     // Set the information of the selected profile
     // in the environment variable at the end of execution
     let shell = get_shell();
     // `export`を行う
     shell.setenv("AWS_PROFILE", name);
+    // `--aws-dir`が指定されている場合は`aws`コマンドでも同じファイルを参照させる
+    if utils::file::custom_aws_dir().is_some() {
+        let config_path = utils::file::aws_file_path(CONFIG_FILE_NAME)?;
+        let credential_path = utils::file::aws_file_path(CREDENTIAL_FILE_NAME)?;
+        shell.setenv("AWS_CONFIG_FILE", config_path.display().to_string());
+        shell.setenv(
+            "AWS_SHARED_CREDENTIALS_FILE",
+            credential_path.display().to_string(),
+        );
+    }
 
     Ok(())
 }
@@ -265,15 +669,17 @@ pub fn list() -> Result<(), Box<dyn std::error::Error>> {
     prompter.flush();
 
     // credentialsファイル読み込み
-    let credentials = read_credential(&mut prompter);
+    let credentials = read_credential(&mut prompter)?;
 
     // ベースとなるcredentialのみ取得
     let mut bases = credentials.bases;
     bases.sort_by(|a, b| a.name.cmp(&b.name));
 
     // 現在のプロファイルを取得
-    let selected = read_tool(&mut prompter);
+    let selected = read_tool(&mut prompter)?;
     let profile = selected.items.get("selected");
+    // プロファイルごとの`refresh_before`を取得するためにconfigファイル読み込み
+    let configs = read_config(&mut prompter)?;
 
     // 表示するためのテーブル
     let mut table = Table::new();
@@ -283,7 +689,8 @@ pub fn list() -> Result<(), Box<dyn std::error::Error>> {
         cell!("ACCOUNT"),
         cell!("MFA"),
         cell!("ROLE ARN"),
-        cell!("EXPIRATION")
+        cell!("EXPIRATION"),
+        cell!("STATUS")
     ]);
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
@@ -295,6 +702,10 @@ pub fn list() -> Result<(), Box<dyn std::error::Error>> {
         } else {
             "".to_string()
         };
+        // 期限切れとして扱う残り時間をもとにした状態
+        let config = configs.items.get(&cred.name).cloned().unwrap_or_default();
+        let refresh_before = refresh_before(&config, &selected)?;
+        let status = expiration_status(&cred, refresh_before);
         // アカウント情報
         let account = cred.account.unwrap_or_else(|| "".to_string());
         // MFA
@@ -312,6 +723,7 @@ pub fn list() -> Result<(), Box<dyn std::error::Error>> {
             cell!(mfa),
             cell!(role),
             cell!(expiration),
+            cell!(status),
         ]);
     }
 
@@ -329,51 +741,453 @@ pub fn list() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// `config`ファイル読み込み
-pub fn read_config(prompter: &mut utils::prompt::Prompter) -> AWSConfigs {
-    // ファイル読み込み
-    let result = utils::file::read::<AWSConfigs, Config>(CONFIG_FILE_NAME);
-    // 読み込みに失敗した場合は`aws configure`を行うかどうかを確認
-    if result.is_err() {
-        prompter.error(format!("{}", result.err().unwrap()).as_str());
-        exit(1);
+/// バックアップの一覧を表示する
+pub fn backups() -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+
+    let list = utils::backup::list(None)?;
+    if list.is_empty() {
+        prompter.standard("No backup has been created yet.");
+        return Ok(());
     }
 
-    // AWSConfigs返却
-    result.unwrap()
+    // 表示するためのテーブル
+    let mut table = Table::new();
+    table.set_titles(row![cell!("ID"), cell!("FILE"), cell!("CREATED")]);
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    for backup in list {
+        table.add_row(row![
+            cell!(backup.id),
+            cell!(backup.file_name),
+            cell!(backup.created.format("%Y-%m-%d %H:%M:%S")),
+        ]);
+    }
+
+    // コンソールに出力
+    let mut writer = super::utils::prompt::StringWriter::new();
+    table.print(&mut writer)?;
+    let data = writer
+        .as_vec()
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>();
+    let out = data[..(data.len() - 1)].to_vec();
+    prompter.standard(out.join("\n").as_str());
+
+    Ok(())
+}
+
+/// バックアップからファイルを復元する
+pub fn restore(backup: Option<String>, diff_only: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // 対象のバックアップを取得
+    let target = if let Some(id) = backup {
+        match utils::backup::find(&id)? {
+            Some(target) => target,
+            None => {
+                return Err(Box::new(AppError::Validation(format!(
+                    "Oops... backup [{}] does not exists..",
+                    id
+                ))));
+            }
+        }
+    } else {
+        let list = utils::backup::list(None)?;
+        if list.is_empty() {
+            return Err(Box::new(AppError::Validation(
+                "No backup has been created yet.".to_string(),
+            )));
+        }
+        // コンソールに選択プロンプトを表示
+        let selections = list.iter().map(|x| x.id.clone()).collect::<Vec<String>>();
+        let index =
+            prompter.select_prompt(&selections, "Please select the backup you want to restore")?;
+        list[index].clone()
+    };
+
+    // 復元した場合の変更内容を表示
+    // 暗号化した保管庫のようにテキストでないファイルは差分を表示しない
+    match (utils::file::read_text(&target.file_name), target.read()) {
+        (Ok(current), Ok(text)) => prompter.diff(&current, &text, &target.file_name, &target.id),
+        _ => prompter.standard("The diff cannot be shown for an encrypted file."),
+    }
+    if diff_only {
+        return Ok(());
+    }
+
+    // 確認フォーム表示
+    let msg = format!("Restore [{}] from [{}]?", target.file_name, target.id);
+    if !prompter.confirm_prompt(&msg)? {
+        return Ok(());
+    }
+    utils::file::restore(&target)?;
+
+    print_complete(&mut prompter, "complete! restored file.", None);
+
+    Ok(())
+}
+
+/// AWS関連ファイルのパーミッションを所有者のみに制限する
+pub fn fix_permissions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+
+    // ディレクトリは0700、ファイルは0600にする
+    let mut targets = vec![
+        (utils::file::aws_dir()?, 0o700),
+        (utils::backup::backup_dir()?, 0o700),
+    ];
+    for name in [
+        CONFIG_FILE_NAME,
+        CREDENTIAL_FILE_NAME,
+        TOOL_FILE_NAME,
+        vault::VAULT_FILE_NAME,
+    ] {
+        targets.push((utils::file::aws_file_path(name)?, 0o600));
+    }
+    for backup in utils::backup::list(None)? {
+        targets.push((backup.path, 0o600));
+    }
+
+    let mut fixed = false;
+    for (path, mode) in targets {
+        if path.exists() && utils::file::restrict_permissions(&path, mode)? {
+            prompter.keyvalue("Fixed permissions", path.display().to_string().as_str());
+            fixed = true;
+        }
+    }
+    if !fixed {
+        prompter.standard("Permissions are already restricted.");
+    }
+
+    Ok(())
+}
+
+/// 所有者以外が読み書きできる状態のファイルの場合は警告を表示
+fn warn_permissions(prompter: &mut utils::prompt::Prompter, file_name: &str) {
+    let Ok(path) = utils::file::aws_file_path(file_name) else {
+        return;
+    };
+    if let Some(mode) = utils::file::loose_mode(&path) {
+        prompter.warning(
+            format!(
+                "Permissions {:04o} for [{}] are too open. Please run `awsst --fix-permissions`.",
+                mode,
+                path.display()
+            )
+            .as_str(),
+        );
+    }
+}
+
+/// `config`ファイル読み込み
+pub fn read_config(
+    prompter: &mut utils::prompt::Prompter,
+) -> Result<AWSConfigs, Box<dyn std::error::Error>> {
+    // 権限が緩い場合は警告を表示
+    warn_permissions(prompter, CONFIG_FILE_NAME);
+    // ファイル読み込み
+    utils::file::read::<AWSConfigs, Config>(CONFIG_FILE_NAME)
 }
 
 /// `credentials`ファイル読み込み
-pub fn read_credential(prompter: &mut utils::prompt::Prompter) -> AWSCredentials {
+pub fn read_credential(
+    prompter: &mut utils::prompt::Prompter,
+) -> Result<AWSCredentials, Box<dyn std::error::Error>> {
+    // 権限が緩い場合は警告を表示
+    warn_permissions(prompter, CREDENTIAL_FILE_NAME);
     // ファイル読み込み
-    let result = utils::file::read::<AWSCredentials, Credential>(CREDENTIAL_FILE_NAME);
-    // 読み込みに失敗した場合は`aws configure`を行うかどうかを確認
-    if result.is_err() {
-        prompter.error(format!("{}", result.err().unwrap()).as_str());
-        exit(1);
+    utils::file::read::<AWSCredentials, Credential>(CREDENTIAL_FILE_NAME)
+}
+
+/// `credentials`ファイルを読み込み、`config`の設定から長期のアクセスキーの保存先を設定する
+pub fn read_credential_with_store(
+    prompter: &mut utils::prompt::Prompter,
+    configs: &AWSConfigs,
+) -> Result<AWSCredentials, Box<dyn std::error::Error>> {
+    let mut credentials = read_credential(prompter)?;
+    credentials.attach_stores(configs, None)?;
+    Ok(credentials)
+}
+
+/// 長期のアクセスキーを暗号化した保管庫に移す
+/// 保管庫が存在しない場合は新たに作成する
+pub fn vault_migrate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // configファイル読み込み
+    let mut configs = read_config(&mut prompter)?;
+    // credentialsファイル読み込み
+    let mut credentials = read_credential(&mut prompter)?;
+
+    // 保管庫が存在しない場合は作成
+    let vault = if Vault::exists() {
+        Vault::open(&prompter)?
+    } else {
+        prompter.standard("Create a new vault. Please enter the passphrase.");
+        Vault::create(&prompter)?
+    };
+
+    // 保存先が未設定で、長期のアクセスキーを平文で保存しているか保管庫に保存済みのプロファイルを保管庫に移す
+    // 移したプロファイルには`credential_store = vault`を設定し、それ以外は`credentials`ファイルのままとする
+    let mut names = Vec::<String>::new();
+    for (name, config) in configs.items.iter_mut() {
+        if config.others.contains_key(store::STORE_KEY) {
+            continue;
+        }
+        let suf_key = format!("{}-{}", name, credentials::KEY_SUFFIX);
+        if credentials.has_long_term_keys(name) || vault.get(&suf_key)?.is_some() {
+            config
+                .others
+                .insert(store::STORE_KEY.to_string(), "vault".to_string());
+            names.push(name.clone());
+        }
+    }
+
+    credentials.attach_stores(&configs, Some(vault))?;
+    let mut moved = 0;
+    for name in &names {
+        if credentials.load(name)? {
+            moved += 1;
+        }
+    }
+
+    // 先に保存先の設定を書き込む
+    // `credentials`の書き込みに失敗してもファイルに残ったアクセスキーは次回の読み込み時に移される
+    configs.write()?;
+    credentials.write()?;
+
+    print_complete(
+        &mut prompter,
+        "complete! moved access keys to the vault",
+        Some(&moved.to_string()),
+    );
+
+    Ok(())
+}
+
+/// 仮想MFAデバイスのシードを登録し、MFAのコードを入力せずに生成できるようにする
+/// シードは`credentials`ファイル以外の保存先にのみ保存する
+pub fn mfa_enroll(profile: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // configファイル読み込み
+    let configs = read_config(&mut prompter)?;
+    // credentialsファイル読み込み
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
+
+    // 対象のConfig名を取得
+    let name = configs.selection_config_name(profile, &mut prompter)?;
+    if !credentials.exists_credential(name.clone()) {
+        return Err(missing_credential(&name));
+    }
+    // 平文の`credentials`ファイルにはシードを保存しない
+    if credentials.store_kind(&name) == store::StoreKind::File {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... the MFA seed of profile [{}] can only be kept in the vault or a credential store command. Run `awsst vault migrate` or set {} first..",
+            name,
+            store::STORE_KEY
+        ))));
+    }
+    // 長期のアクセスキーを保存先から読み込み、サフィックスつきの情報がない場合は生成する
+    credentials.load(&name)?;
+    let cred = credentials.original_credential(&name);
+    let Some(mfa_serial) = cred.mfa_serial.clone() else {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] has no MFA device. Set the MFA device ARN with `awsst update -p {}` first..",
+            name, name
+        ))));
+    };
+
+    // シードを入力させる
+    let seed = prompter.password_prompt(
+        &format!("Base32 seed of MFA device [{}]", mfa_serial),
+        false,
+    )?;
+    let seed = seed.split_whitespace().collect::<String>();
+    let key =
+        utils::totp::decode_seed(&seed).map_err(|e| AppError::Parse(format!("Oops... {}..", e)))?;
+
+    // 登録前にデバイスと同じコードを生成できるか確認してもらう
+    let step = utils::totp::step_at(chrono::Utc::now().timestamp());
+    prompter.keyvalue("Current MFA code", &utils::totp::code(&key, step));
+    if !prompter.confirm_prompt("Does it match the code shown on the device?")? {
+        return Err(Box::new(AppError::Cancelled));
     }
+    cred.mfa_totp_seed = Some(seed);
+
+    // ファイル書き込みを行う
+    credentials.write()?;
 
-    // AWSCredentials返却
-    result.unwrap()
+    print_complete(
+        &mut prompter,
+        "complete! MFA codes will be generated for profile",
+        Some(&name),
+    );
+
+    Ok(())
 }
 
-/// ツール用のファイル読み込み
-pub fn read_tool(prompter: &mut utils::prompt::Prompter) -> AWSSelecteds {
-    // ファイル読み込み
-    let result = utils::file::read::<AWSSelecteds, Selected>(TOOL_FILE_NAME);
-    // 読み込みに失敗した場合は`aws configure`を行うかどうかを確認
-    if result.is_err() {
-        prompter.error(format!("{}", result.err().unwrap()).as_str());
-        exit(1);
+/// 登録した仮想MFAデバイスのシードを削除し、MFAのコードを入力する方式に戻す
+pub fn mfa_remove(profile: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut prompter = utils::prompt::Prompter::new();
+    // Ctrl+Cのハンドラーを登録
+    prompter.flush();
+    // 書き込みが完了するまで他のプロセスからの更新を防ぐ
+    let _lock = utils::file::lock(&mut prompter)?;
+
+    // configファイル読み込み
+    let configs = read_config(&mut prompter)?;
+    // credentialsファイル読み込み
+    let mut credentials = read_credential_with_store(&mut prompter, &configs)?;
+
+    // 対象のConfig名を取得
+    let name = configs.selection_config_name(profile, &mut prompter)?;
+    if !credentials.exists_credential(name.clone()) {
+        return Err(missing_credential(&name));
+    }
+    credentials.load(&name)?;
+    let cred = credentials.suffix_credential(name.clone()).unwrap();
+    if cred.mfa_totp_seed.take().is_none() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] has no MFA seed..",
+            name
+        ))));
     }
 
-    // AWSCredentials返却
-    result.unwrap()
+    // ファイル書き込みを行う
+    credentials.write()?;
+
+    print_complete(
+        &mut prompter,
+        "complete! removed the MFA seed of profile",
+        Some(&name),
+    );
+
+    Ok(())
+}
+
+/// ツール用のファイル読み込み
+pub fn read_tool(
+    prompter: &mut utils::prompt::Prompter,
+) -> Result<AWSSelecteds, Box<dyn std::error::Error>> {
+    // 権限が緩い場合は警告を表示
+    warn_permissions(prompter, TOOL_FILE_NAME);
+    // ファイル読み込み
+    utils::file::read::<AWSSelecteds, Selected>(TOOL_FILE_NAME)
 }
 
 /// ツール用のファイルを設定
+/// `[settings]`などの選択中のプロファイル以外の内容は保持する
 fn _set_tool_file(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let selecteds = new_selected(config.name.clone(), config.region.clone());
+    let name = config.name.clone();
+    let region = config.region.clone().unwrap_or_default();
+    // 読み込めない場合は`[settings]`や`[mfa]`を失わないよう書き込まずにエラーとする
+    let mut selecteds = utils::file::read::<AWSSelecteds, Selected>(TOOL_FILE_NAME)?;
+    selecteds.select(name, region);
     // ファイル書き込みを行う
     selecteds.write()
 }
+
+/// MFAデバイスのコードが最後に受け付けられた日時(UNIX時間)を取得
+/// ツール用のファイルを読み込めない場合や未記録の場合は`None`
+pub fn mfa_last_used(mfa_serial: &str) -> Option<i64> {
+    utils::file::read::<AWSSelecteds, Selected>(TOOL_FILE_NAME)
+        .ok()?
+        .mfa_last_used(mfa_serial)
+}
+
+/// MFAデバイスのコードが受け付けられた日時をツール用のファイルに記録
+/// 認証情報の取得は成功しているため、記録できない場合も処理は続ける
+pub fn record_mfa_use(mfa_serial: &str, time: i64) {
+    // `--dry-run`では差分の表示が煩雑になるため記録しない
+    if utils::file::is_dry_run() {
+        return;
+    }
+    let Ok(mut selecteds) = utils::file::read::<AWSSelecteds, Selected>(TOOL_FILE_NAME) else {
+        return;
+    };
+    selecteds.set_mfa_last_used(mfa_serial, time);
+    let _ = selecteds.write();
+}
+
+/// 期限の状態を表示用の文字列に変換
+/// 期限切れとして扱う残り時間を下回っている場合は次回の`session`で更新される
+fn expiration_status(cred: &Credential, refresh_before: i32) -> String {
+    if cred.expiration.is_none() {
+        return "".to_string();
+    }
+    // 表示は分単位にする
+    let left = |remaining: i64| utils::duration::format_seconds(remaining.max(60) / 60 * 60);
+    match cred.remaining_seconds() {
+        Some(remaining) if remaining <= 0 => "expired".to_string(),
+        Some(remaining) if cred.is_expired(refresh_before) => {
+            format!("refresh ({} left)", left(remaining))
+        }
+        Some(remaining) => format!("valid ({} left)", left(remaining)),
+        None => "invalid".to_string(),
+    }
+}
+
+/// 完了メッセージを表示
+/// `--dry-run`の場合は何も書き込んでいないため、その旨を表示する
+fn print_complete(prompter: &mut utils::prompt::Prompter, message: &str, value: Option<&str>) {
+    if utils::file::is_dry_run() {
+        prompter.standard("(dry-run) nothing written.");
+        return;
+    }
+    match value {
+        Some(value) => prompter.keyvalue(message, value),
+        None => prompter.standard(message),
+    }
+}
+
+/// 取得したセッションの期限を表示
+/// `--dry-run`の場合は取得したセッションを書き込まないため、期限の代わりにその旨を表示する
+fn print_expiration(prompter: &mut utils::prompt::Prompter, expiration: Option<&str>) {
+    if expiration.is_some() || utils::file::is_dry_run() {
+        print_complete(prompter, "Success! Token expiration is ", expiration);
+    }
+}
+
+/// 指定のプロファイルが`credentials`に存在しない場合のエラー
+fn missing_credential(name: &str) -> Box<dyn std::error::Error> {
+    Box::new(AppError::Validation(format!(
+        "Oops... profile [{}] does not exists in credentials..",
+        name
+    )))
+}
+
+/// 取得したセッションで`credentials`を更新できなかった場合のエラー
+fn failed_update(name: &str) -> Box<dyn std::error::Error> {
+    Box::new(AppError::Validation(format!(
+        "Oops... failed update credential of profile [{}]..",
+        name
+    )))
+}
+
+/// 期限切れとして扱う残り時間(秒)を取得
+/// プロファイルの`refresh_before`、ツール用のファイルの`[settings]`の`refresh_before`の順に優先する
+fn refresh_before(config: &Config, selecteds: &AWSSelecteds) -> Result<i32, AppError> {
+    if let Some(refresh_before) = config.refresh_before().map_err(AppError::Parse)? {
+        return Ok(refresh_before);
+    }
+    Ok(selecteds
+        .refresh_before()
+        .map_err(AppError::Parse)?
+        .unwrap_or(credentials::DEFAULT_REFRESH_BEFORE))
+}
@@ -0,0 +1,173 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use std::io::Read;
+
+use crate::error::AppError;
+use crate::utils;
+
+/// 引き受けるロールを指定するSAMLの属性名
+const ROLE_ATTRIBUTE: &str = "https://aws.amazon.com/SAML/Attributes/Role";
+
+/// SAMLアサーションで引き受けられるロールとIdPの組み合わせ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamlRole {
+    pub role_arn: String,
+    pub principal_arn: String,
+}
+
+/// base64でエンコードされたSAMLアサーションを読み込む
+/// `-`の場合は標準入力から読み込む
+pub fn read_assertion(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let text = if path == "-" {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        std::fs::read_to_string(utils::file::expand_home(path)).map_err(|e| {
+            AppError::File(format!(
+                "Oops... cannot read the SAML assertion [{}]: {}..",
+                path, e
+            ))
+        })?
+    };
+
+    // 折り返しの改行などは取り除く
+    let assertion = text.split_whitespace().collect::<String>();
+    if assertion.is_empty() {
+        return Err(Box::new(AppError::Parse(
+            "Oops... the SAML assertion is empty..".to_string(),
+        )));
+    }
+    Ok(assertion)
+}
+
+/// SAMLアサーションに含まれるロールとIdPの組み合わせを取得
+pub fn roles(assertion: &str) -> Result<Vec<SamlRole>, Box<dyn std::error::Error>> {
+    let xml = STANDARD
+        .decode(assertion)
+        .ok()
+        .and_then(|x| String::from_utf8(x).ok())
+        .ok_or_else(|| {
+            AppError::Parse("Oops... the SAML assertion is not base64 encoded XML..".to_string())
+        })?;
+
+    // 名前空間の接頭辞はIdPによって異なるため任意とする
+    let re_attribute = Regex::new(&format!(
+        r#"(?s)<(?:\w+:)?Attribute\s[^>]*Name=["']{}["'][^>]*>(.*?)</(?:\w+:)?Attribute>"#,
+        regex::escape(ROLE_ATTRIBUTE)
+    ))
+    .unwrap();
+    let re_value =
+        Regex::new(r"(?s)<(?:\w+:)?AttributeValue[^>]*>(.*?)</(?:\w+:)?AttributeValue>").unwrap();
+
+    let mut roles = Vec::<SamlRole>::new();
+    for attribute in re_attribute.captures_iter(&xml) {
+        for value in re_value.captures_iter(&attribute[1]) {
+            // ロールとIdPの順序はIdPによって異なるため、ARNの種類で判定する
+            let mut role_arn = None;
+            let mut principal_arn = None;
+            for arn in value[1].split(',').map(|x| x.trim()) {
+                if arn.contains(":role/") {
+                    role_arn = Some(arn.to_string());
+                } else if arn.contains(":saml-provider/") {
+                    principal_arn = Some(arn.to_string());
+                }
+            }
+            let (Some(role_arn), Some(principal_arn)) = (role_arn, principal_arn) else {
+                continue;
+            };
+            let role = SamlRole {
+                role_arn,
+                principal_arn,
+            };
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+    }
+
+    if roles.is_empty() {
+        return Err(Box::new(AppError::Validation(
+            "Oops... the SAML assertion does not contain any AWS role..".to_string(),
+        )));
+    }
+    Ok(roles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLE_A: &str = "arn:aws:iam::123456789012:role/Admin";
+    const ROLE_B: &str = "arn:aws:iam::123456789012:role/ReadOnly";
+    const IDP: &str = "arn:aws:iam::123456789012:saml-provider/Corp";
+
+    /// 指定した属性を持つSAMLレスポンスをbase64でエンコードする
+    fn assertion(attributes: &str) -> String {
+        let xml = format!(
+            r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol"><saml2:Assertion xmlns:saml2="urn:oasis:names:tc:SAML:2.0:assertion"><saml2:AttributeStatement>{}</saml2:AttributeStatement></saml2:Assertion></samlp:Response>"#,
+            attributes
+        );
+        STANDARD.encode(xml)
+    }
+
+    /// ロール属性を生成する
+    fn role_attribute(values: &[String]) -> String {
+        let values = values
+            .iter()
+            .map(|x| format!("<saml2:AttributeValue>{}</saml2:AttributeValue>", x))
+            .collect::<String>();
+        format!(
+            r#"<saml2:Attribute Name="{}" NameFormat="urn:oasis:names:tc:SAML:2.0:attrname-format:uri">{}</saml2:Attribute>"#,
+            ROLE_ATTRIBUTE, values
+        )
+    }
+
+    fn role(role_arn: &str) -> SamlRole {
+        SamlRole {
+            role_arn: role_arn.to_string(),
+            principal_arn: IDP.to_string(),
+        }
+    }
+
+    #[test]
+    fn roles_accept_role_then_principal() {
+        let assertion = assertion(&role_attribute(&[format!("{},{}", ROLE_A, IDP)]));
+        assert_eq!(roles(&assertion).unwrap(), vec![role(ROLE_A)]);
+    }
+
+    #[test]
+    fn roles_accept_principal_then_role() {
+        let assertion = assertion(&role_attribute(&[format!("{}, {}", IDP, ROLE_A)]));
+        assert_eq!(roles(&assertion).unwrap(), vec![role(ROLE_A)]);
+    }
+
+    #[test]
+    fn roles_collect_multiple_roles_without_duplicates() {
+        let assertion = assertion(&role_attribute(&[
+            format!("{},{}", ROLE_A, IDP),
+            format!("{},{}", IDP, ROLE_B),
+            format!("{},{}", ROLE_A, IDP),
+        ]));
+        assert_eq!(roles(&assertion).unwrap(), vec![role(ROLE_A), role(ROLE_B)]);
+    }
+
+    #[test]
+    fn roles_fail_without_role_attribute() {
+        let assertion = assertion(
+            r#"<saml2:Attribute Name="https://aws.amazon.com/SAML/Attributes/RoleSessionName"><saml2:AttributeValue>john</saml2:AttributeValue></saml2:Attribute>"#,
+        );
+        assert!(roles(&assertion).is_err());
+    }
+
+    #[test]
+    fn roles_fail_on_invalid_base64() {
+        assert!(roles("not base64 !!").is_err());
+    }
+
+    #[test]
+    fn roles_fail_on_invalid_xml() {
+        assert!(roles(&STANDARD.encode("<samlp:Response><broken")).is_err());
+        assert!(roles(&STANDARD.encode([0xff, 0xfe, 0xfd])).is_err());
+    }
+}
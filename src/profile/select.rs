@@ -1,48 +1,123 @@
-use crate::utils::{AWSFile, AWSFileManager};
-use std::collections::HashMap;
+use crate::utils::{self, document::AWSDocument, AWSFile, AWSFileManager};
+use std::collections::{BTreeMap, HashMap};
+
+/// ツール全体の設定を保存するセクション名
+const SETTINGS_SECTION: &str = "settings";
+/// 期限切れとして扱う残り時間を指定するキー
+const REFRESH_BEFORE_KEY: &str = "refresh_before";
+/// MFAデバイスごとにコードが最後に受け付けられた日時を保存するセクション名
+const MFA_SECTION: &str = "mfa";
 
 /// AWS Configファイル情報
 pub struct AWSSelecteds {
     pub items: HashMap<String, Selected>,
+    // `[settings]`セクションに設定されたツール全体の設定
+    pub settings: BTreeMap<String, String>,
+    // `[mfa]`セクションに保存したMFAデバイスのARNとコードが最後に受け付けられた日時(UNIX時間)
+    mfa_used: BTreeMap<String, String>,
+    // 読み込み時のファイル内容
+    document: AWSDocument,
 }
 
-/// 存在しない場合に作成する
-pub fn new_selected(name: String, region: String) -> AWSSelecteds {
-    let mut items = HashMap::<String, Selected>::new();
-    items.insert("selected".to_string(), Selected { name, region });
-    AWSSelecteds { items }
+impl AWSSelecteds {
+    /// 選択中のプロファイルを設定
+    pub fn select(&mut self, name: String, region: String) {
+        self.items
+            .insert("selected".to_string(), Selected { name, region });
+    }
+
+    /// `refresh_before`に設定された期限切れとして扱う残り時間(秒)を取得
+    /// 未設定の場合は`None`
+    pub fn refresh_before(&self) -> Result<Option<i32>, String> {
+        let Some(val) = self.settings.get(REFRESH_BEFORE_KEY) else {
+            return Ok(None);
+        };
+        utils::duration::parse_seconds(val).map(Some).map_err(|e| {
+            format!(
+                "Oops... {} of [{}] in the tool file: {}..",
+                REFRESH_BEFORE_KEY, SETTINGS_SECTION, e
+            )
+        })
+    }
+
+    /// 指定のMFAデバイスのコードが最後に受け付けられた日時(UNIX時間)を取得
+    /// 未記録の場合は`None`
+    pub fn mfa_last_used(&self, mfa_serial: &str) -> Option<i64> {
+        self.mfa_used.get(mfa_serial)?.parse::<i64>().ok()
+    }
+
+    /// 指定のMFAデバイスのコードが受け付けられた日時(UNIX時間)を記録
+    pub fn set_mfa_last_used(&mut self, mfa_serial: &str, time: i64) {
+        self.mfa_used
+            .insert(mfa_serial.to_string(), time.to_string());
+    }
 }
 
 impl AWSFileManager<Selected> for AWSSelecteds {
-    fn new(val: HashMap<String, HashMap<String, String>>) -> AWSSelecteds {
+    fn new(document: AWSDocument) -> AWSSelecteds {
         let mut items = HashMap::<String, Selected>::new();
-        for (key, ele) in val {
+        let mut settings = BTreeMap::<String, String>::new();
+        let mut mfa_used = BTreeMap::<String, String>::new();
+        for (key, ele) in document.to_map() {
+            // ツール全体の設定はそのまま保持する
+            if key == SETTINGS_SECTION {
+                settings = ele.into_iter().collect();
+                continue;
+            }
+            if key == MFA_SECTION {
+                mfa_used = ele.into_iter().collect();
+                continue;
+            }
             items.insert(
                 key,
                 Selected {
-                    name: ele["name"].clone(),
-                    region: ele["region"].clone(),
+                    name: ele.get("name").cloned().unwrap_or_default(),
+                    region: ele.get("region").cloned().unwrap_or_default(),
                 },
             );
         }
-        AWSSelecteds { items }
+        AWSSelecteds {
+            items,
+            settings,
+            mfa_used,
+            document,
+        }
     }
 
-    /// ファイル出力用にMapを生成
-    fn to_file(&self) -> HashMap<String, HashMap<String, String>> {
-        let mut list = HashMap::<String, HashMap<String, String>>::new();
+    /// ファイル出力用にセクションのリストを生成
+    fn to_file(&self) -> Vec<(String, Vec<(String, String)>)> {
+        let mut list = Vec::<(String, Vec<(String, String)>)>::new();
         for ele in &self.items {
-            list.insert(ele.0.clone(), ele.1.to_file_map());
+            list.push((ele.0.clone(), ele.1.to_file_map()));
         }
+        if !self.settings.is_empty() {
+            let values = self
+                .settings
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            list.push((SETTINGS_SECTION.to_string(), values));
+        }
+        if !self.mfa_used.is_empty() {
+            let values = self
+                .mfa_used
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            list.push((MFA_SECTION.to_string(), values));
+        }
+        // 新たに追加するセクションの順序を固定するためにソート
+        list.sort_by(|a, b| a.0.cmp(&b.0));
         list
     }
 
     /// ツール用ファイル書き込み
     fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // 書き込み用に整形
-        let data = self.to_file();
+        // 読み込み時のドキュメントに変更内容を反映
+        let mut document = self.document.clone();
+        document.merge(&self.to_file());
         // ファイル書き込み
-        super::utils::file::write(super::TOOL_FILE_NAME, data, false)
+        super::utils::file::write(super::TOOL_FILE_NAME, &document, false)
     }
 
     fn add(&mut self, data: Selected) {
@@ -62,10 +137,10 @@ pub struct Selected {
 }
 
 impl AWSFile for Selected {
-    fn to_file_map(&self) -> HashMap<String, String> {
-        let mut list = HashMap::<String, String>::new();
-        list.insert("name".to_string(), self.name.clone());
-        list.insert("region".to_string(), self.region.clone());
-        list
+    fn to_file_map(&self) -> Vec<(String, String)> {
+        vec![
+            ("name".to_string(), self.name.clone()),
+            ("region".to_string(), self.region.clone()),
+        ]
     }
 }
@@ -0,0 +1,320 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+};
+
+use super::configs::AWSConfigs;
+use super::credentials::{Credential, KEY_SUFFIX};
+use super::vault::Vault;
+use crate::error::AppError;
+use crate::utils::{self, document::AWSDocument, prompt::Prompter, AWSFile};
+
+/// 長期のアクセスキーの保存先を指定する`config`のキー
+pub const STORE_KEY: &str = "credential_store";
+/// 外部コマンドの保存先で認証情報を取得するコマンドを指定するキー
+pub const GET_COMMAND_KEY: &str = "credential_store_get";
+/// 外部コマンドの保存先で認証情報を保存するコマンドを指定するキー
+pub const PUT_COMMAND_KEY: &str = "credential_store_put";
+/// 外部コマンドの保存先で認証情報を削除するコマンドを指定するキー
+pub const DELETE_COMMAND_KEY: &str = "credential_store_delete";
+
+/// 長期のアクセスキーを保存するストレージ
+/// 認証情報の名称はサフィックスつきの名称を利用する
+pub trait CredentialStore {
+    /// 指定の名称の認証情報を取得
+    /// 存在しない場合は`None`を返却
+    fn get(&self, name: &str) -> Result<Option<Credential>, Box<dyn std::error::Error>>;
+    /// 認証情報を保存する
+    fn put(&self, cred: &Credential) -> Result<(), Box<dyn std::error::Error>>;
+    /// 指定の名称の認証情報を削除する
+    fn delete(&self, name: &str) -> Result<(), Box<dyn std::error::Error>>;
+    /// 保存した内容を書き込む
+    fn flush(&self) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// 保存先の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    // `credentials`ファイル
+    File,
+    // 暗号化した保管庫
+    Vault,
+    // `pass`や`gopass`などの外部コマンド
+    Command,
+}
+
+impl StoreKind {
+    /// `credential_store`の値から種類を取得
+    fn parse(profile: &str, val: &str) -> Result<StoreKind, Box<dyn std::error::Error>> {
+        match val {
+            "file" => Ok(StoreKind::File),
+            "vault" => Ok(StoreKind::Vault),
+            "command" => Ok(StoreKind::Command),
+            _ => Err(Box::new(AppError::Parse(format!(
+                "Oops... unknown {} [{}] for profile [{}]. Use `file`, `vault` or `command`..",
+                STORE_KEY, val, profile
+            )))),
+        }
+    }
+}
+
+/// `credentials`ファイルにサフィックスつきのセクションとして保存する
+/// ファイルへの書き込みは`AWSCredentials`がベースの情報とまとめて行う
+pub struct FileStore {
+    items: RefCell<Vec<Credential>>,
+}
+
+impl FileStore {
+    pub fn new(items: Vec<Credential>) -> FileStore {
+        FileStore {
+            items: RefCell::new(items),
+        }
+    }
+
+    /// ファイル出力用にセクションのリストを生成
+    pub fn to_file(&self) -> Vec<(String, Vec<(String, String)>)> {
+        self.items
+            .borrow()
+            .iter()
+            .map(|ele| (ele.name.clone(), ele.to_file_map()))
+            .collect()
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn get(&self, name: &str) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+        Ok(self
+            .items
+            .borrow()
+            .iter()
+            .find(|ele| ele.name == name)
+            .cloned())
+    }
+
+    fn put(&self, cred: &Credential) -> Result<(), Box<dyn std::error::Error>> {
+        let mut items = self.items.borrow_mut();
+        match items.iter_mut().find(|ele| ele.name == cred.name) {
+            Some(ele) => *ele = cred.clone(),
+            None => items.push(cred.clone()),
+        }
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.items.borrow_mut().retain(|ele| ele.name != name);
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// 外部コマンドで認証情報を取得、保存する
+/// コマンドとは`credentials`ファイルのセクション内と同じ`key = value`形式でやり取りする
+pub struct CommandStore {
+    profile: String,
+    get: String,
+    put: String,
+    delete: Option<String>,
+    // 取得時の内容. 変更がない場合は保存コマンドを実行しない
+    fetched: RefCell<HashMap<String, Vec<(String, String)>>>,
+}
+
+impl CommandStore {
+    /// `config`に設定されたコマンドから生成
+    fn from_config(
+        profile: &str,
+        values: &BTreeMap<String, String>,
+    ) -> Result<CommandStore, Box<dyn std::error::Error>> {
+        let command = |key: &str| values.get(key).filter(|val| !val.is_empty()).cloned();
+        let (Some(get), Some(put)) = (command(GET_COMMAND_KEY), command(PUT_COMMAND_KEY)) else {
+            return Err(Box::new(AppError::Validation(format!(
+                "Oops... profile [{}] needs {} and {} to use the command store..",
+                profile, GET_COMMAND_KEY, PUT_COMMAND_KEY
+            ))));
+        };
+        Ok(CommandStore {
+            profile: profile.to_string(),
+            get,
+            put,
+            delete: command(DELETE_COMMAND_KEY),
+            fetched: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// シェル経由でコマンドを実行し、標準出力を返却
+    fn run(
+        &self,
+        command: &str,
+        input: Option<&str>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        utils::command::run(command, &self.profile, input).map_err(|e| {
+            Box::new(AppError::File(format!(
+                "Oops... credential store command failed for profile [{}]: `{}`\n{}",
+                self.profile, command, e
+            ))) as Box<dyn std::error::Error>
+        })
+    }
+}
+
+impl CredentialStore for CommandStore {
+    fn get(&self, name: &str) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+        let text = self.run(&self.get, None)?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+
+        // セクションのヘッダを付与して`credentials`ファイルと同様に解析する
+        let document = AWSDocument::parse(&format!("[{}]\n{}", name, text)).map_err(|e| {
+            AppError::Parse(format!(
+                "Oops... invalid output of credential store command for profile [{}]: {}",
+                self.profile, e.message
+            ))
+        })?;
+        let values = document.to_map().remove(name).unwrap_or_default();
+        let cred = Credential::from_map(name.to_string(), &values);
+        self.fetched
+            .borrow_mut()
+            .insert(name.to_string(), cred.to_file_map());
+        Ok(Some(cred))
+    }
+
+    fn put(&self, cred: &Credential) -> Result<(), Box<dyn std::error::Error>> {
+        let values = cred.to_file_map();
+        if self.fetched.borrow().get(&cred.name) == Some(&values) {
+            return Ok(());
+        }
+        // `--dry-run`の場合はコマンドを実行しない
+        if utils::file::is_dry_run() {
+            Prompter::new().keyvalue("Would run credential store command", &self.put);
+            return Ok(());
+        }
+
+        let text = values
+            .iter()
+            .map(|(key, val)| format!("{} = {}\n", key, val))
+            .collect::<String>();
+        self.run(&self.put, Some(&text))?;
+        self.fetched.borrow_mut().insert(cred.name.clone(), values);
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(command) = &self.delete {
+            if utils::file::is_dry_run() {
+                Prompter::new().keyvalue("Would run credential store command", command);
+                return Ok(());
+            }
+            self.run(command, None)?;
+        }
+        self.fetched.borrow_mut().remove(name);
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// プロファイルごとの長期のアクセスキーの保存先
+/// `credential_store`が未設定のプロファイルは`credentials`ファイルに保存する
+/// 保管庫は`awsst vault migrate`で`credential_store = vault`を設定したプロファイルのみ利用する
+pub struct Stores {
+    file: FileStore,
+    vault: Option<Vault>,
+    // プロファイル名ごとの外部コマンド
+    commands: HashMap<String, CommandStore>,
+    // プロファイル名ごとの保存先の種類
+    kinds: HashMap<String, StoreKind>,
+}
+
+impl Stores {
+    /// すべて`credentials`ファイルに保存する
+    pub fn new(file: FileStore) -> Stores {
+        Stores {
+            file,
+            vault: None,
+            commands: HashMap::new(),
+            kinds: HashMap::new(),
+        }
+    }
+
+    /// `config`の設定から保存先を設定する
+    /// 外部コマンドの実行や保管庫の復号は、利用するプロファイルを読み込むまで行わない
+    pub fn configure(
+        &mut self,
+        configs: &AWSConfigs,
+        vault: Option<Vault>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (name, config) in &configs.items {
+            let kind = match config.others.get(STORE_KEY) {
+                Some(val) => StoreKind::parse(name, val)?,
+                None => StoreKind::File,
+            };
+            if kind == StoreKind::Command {
+                self.commands.insert(
+                    name.clone(),
+                    CommandStore::from_config(name, &config.others)?,
+                );
+            }
+            self.kinds.insert(name.clone(), kind);
+        }
+        self.vault = vault;
+        Ok(())
+    }
+
+    /// プロファイルの保存先を利用できるようにする
+    /// 保管庫を利用するプロファイルの場合、開いていなければ保管庫を開く
+    pub fn open(&mut self, profile: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.kind(profile) != StoreKind::Vault || self.vault.is_some() {
+            return Ok(());
+        }
+        if !Vault::exists() {
+            return Err(Box::new(AppError::Validation(
+                "Oops... the vault does not exist. Please create it with `awsst vault migrate`.."
+                    .to_string(),
+            )));
+        }
+        self.vault = Some(Vault::open(&Prompter::new())?);
+        Ok(())
+    }
+
+    /// プロファイルの保存先の種類を取得
+    /// `config`に存在しないプロファイルは未設定として扱う
+    pub fn kind(&self, profile: &str) -> StoreKind {
+        self.kinds.get(profile).copied().unwrap_or(StoreKind::File)
+    }
+
+    /// プロファイルの保存先を取得
+    pub fn store(&self, profile: &str) -> &dyn CredentialStore {
+        match (self.kind(profile), &self.vault, self.commands.get(profile)) {
+            (StoreKind::Vault, Some(vault), _) => vault,
+            (StoreKind::Command, _, Some(command)) => command,
+            _ => &self.file,
+        }
+    }
+
+    /// `credentials`ファイルに保存する保存先
+    pub fn file(&self) -> &FileStore {
+        &self.file
+    }
+
+    /// すべての保存先の内容を書き込む
+    pub fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(vault) = &self.vault {
+            vault.flush()?;
+        }
+        for command in self.commands.values() {
+            command.flush()?;
+        }
+        self.file.flush()
+    }
+}
+
+/// サフィックスつきの名称からプロファイル名を取得
+pub fn profile_name(name: &str) -> &str {
+    name.strip_suffix(KEY_SUFFIX)
+        .and_then(|x| x.strip_suffix('-'))
+        .unwrap_or(name)
+}
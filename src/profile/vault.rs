@@ -0,0 +1,280 @@
+use std::{cell::RefCell, env};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+
+use super::credentials::Credential;
+use super::store::CredentialStore;
+use crate::error::AppError;
+use crate::utils::{self, document::AWSDocument, prompt::Prompter, AWSFile};
+
+/// 暗号化したアクセスキーを保存するファイル名
+pub const VAULT_FILE_NAME: &str = "awsst-vault";
+/// パスフレーズを指定する環境変数
+pub const PASSPHRASE_ENV: &str = "AWSST_VAULT_PASSPHRASE";
+
+/// ファイルの先頭に付与する識別子
+const MAGIC: &[u8; 8] = b"AWSSTVLT";
+/// ファイル形式のバージョン
+const FORMAT_VERSION: u8 = 1;
+/// 鍵導出(Argon2id)のパラメータ: メモリ(KiB), 反復回数, 並列度
+const KDF_PARAMS: (u32, u32, u32) = (64 * 1024, 3, 1);
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// 識別子, バージョン, KDFパラメータ, ソルト, ナンスを合わせたヘッダの長さ
+const HEADER_LEN: usize = 8 + 1 + 12 + SALT_LEN + NONCE_LEN;
+
+/// パスフレーズで暗号化した長期のアクセスキーの保管庫
+/// 復号した内容は`credentials`ファイルと同じ形式で保持する
+pub struct Vault {
+    passphrase: String,
+    // 読み込み時の復号した内容
+    document: AWSDocument,
+    // サフィックスつきの本アプリ用の認証情報
+    items: RefCell<Vec<Credential>>,
+}
+
+impl Vault {
+    /// 保管庫のファイルが存在するか確認
+    pub fn exists() -> bool {
+        utils::file::aws_file_path(VAULT_FILE_NAME).is_ok_and(|path| path.exists())
+    }
+
+    /// 保管庫を新たに作成する
+    /// パスフレーズは確認のため2回入力させる
+    pub fn create(prompter: &Prompter) -> Result<Vault, Box<dyn std::error::Error>> {
+        let passphrase = passphrase(prompter, true)?;
+        Ok(Vault {
+            passphrase,
+            document: AWSDocument::default(),
+            items: RefCell::new(vec![]),
+        })
+    }
+
+    /// 保管庫を読み込み、復号する
+    pub fn open(prompter: &Prompter) -> Result<Vault, Box<dyn std::error::Error>> {
+        let path = utils::file::aws_file_path(VAULT_FILE_NAME)?;
+        let data = std::fs::read(&path)?;
+        let passphrase = passphrase(prompter, false)?;
+
+        let plain = decrypt(&passphrase, &data)?;
+        let text = String::from_utf8(plain).map_err(|e| AppError::Parse(e.to_string()))?;
+        let document = AWSDocument::parse(&text).map_err(|e| e.with_file(&path))?;
+
+        let mut originals = Vec::<Credential>::new();
+        for (key, ele) in document.to_map() {
+            originals.push(Credential::from_map(key, &ele));
+        }
+        originals.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Vault {
+            passphrase,
+            document,
+            items: RefCell::new(originals),
+        })
+    }
+}
+
+impl CredentialStore for Vault {
+    fn get(&self, name: &str) -> Result<Option<Credential>, Box<dyn std::error::Error>> {
+        Ok(self
+            .items
+            .borrow()
+            .iter()
+            .find(|ele| ele.name == name)
+            .cloned())
+    }
+
+    fn put(&self, cred: &Credential) -> Result<(), Box<dyn std::error::Error>> {
+        let mut items = self.items.borrow_mut();
+        match items.iter_mut().find(|ele| ele.name == cred.name) {
+            Some(ele) => *ele = cred.clone(),
+            None => items.push(cred.clone()),
+        }
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.items.borrow_mut().retain(|ele| ele.name != name);
+        Ok(())
+    }
+
+    /// 暗号化してファイルに書き込む
+    fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let list = self
+            .items
+            .borrow()
+            .iter()
+            .map(|ele| (ele.name.clone(), ele.to_file_map()))
+            .collect::<Vec<(String, Vec<(String, String)>)>>();
+        let mut document = self.document.clone();
+        document.merge(&list);
+
+        // `--dry-run`の場合は復号した内容の差分のみ表示する
+        let text = document.to_string();
+        if utils::file::is_dry_run() {
+            let current = self.document.to_string();
+            return utils::file::show_dry_run_diff(VAULT_FILE_NAME, &current, &text);
+        }
+
+        let data = encrypt(&self.passphrase, text.as_bytes(), KDF_PARAMS)?;
+        utils::file::write_bytes(VAULT_FILE_NAME, &data, true)
+    }
+}
+
+/// パスフレーズを取得する
+/// 環境変数に設定されている場合はそれを利用し、未設定の場合は入力させる
+fn passphrase(prompter: &Prompter, confirm: bool) -> Result<String, Box<dyn std::error::Error>> {
+    if let Ok(val) = env::var(PASSPHRASE_ENV) {
+        if !val.is_empty() {
+            return Ok(val);
+        }
+    }
+    prompter.password_prompt("Vault passphrase", confirm)
+}
+
+/// パスフレーズとソルトから暗号鍵を導出する
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: (u32, u32, u32),
+) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let params = Params::new(params.0, params.1, params.2, Some(32))
+        .map_err(|e| AppError::Parse(e.to_string()))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| AppError::Parse(e.to_string()))?;
+    Ok(key)
+}
+
+/// 暗号化してファイルに書き込む形式に変換
+/// ヘッダは改ざんを検知するため認証データとして扱う
+/// 鍵導出のパラメータはヘッダに保存し、復号時はヘッダの値を利用する
+fn encrypt(
+    passphrase: &str,
+    plain: &[u8],
+    params: (u32, u32, u32),
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut data = Vec::<u8>::with_capacity(HEADER_LEN + plain.len() + 16);
+    data.extend_from_slice(MAGIC);
+    data.push(FORMAT_VERSION);
+    for val in [params.0, params.1, params.2] {
+        data.extend_from_slice(&val.to_le_bytes());
+    }
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let payload = Payload {
+        msg: plain,
+        aad: &data,
+    };
+    let encrypted = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| AppError::Other("Oops... failed to encrypt the vault..".to_string()))?;
+    data.extend_from_slice(&encrypted);
+    Ok(data)
+}
+
+/// ファイルの内容を復号する
+fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if data.len() < HEADER_LEN || &data[..8] != MAGIC {
+        return Err(Box::new(AppError::Parse(
+            "Oops... the vault file is broken..".to_string(),
+        )));
+    }
+    if data[8] != FORMAT_VERSION {
+        return Err(Box::new(AppError::Parse(format!(
+            "Oops... unsupported vault version [{}]..",
+            data[8]
+        ))));
+    }
+
+    let (header, encrypted) = data.split_at(HEADER_LEN);
+    let param = |i: usize| u32::from_le_bytes(header[9 + i * 4..13 + i * 4].try_into().unwrap());
+    let salt = &header[21..21 + SALT_LEN];
+    let nonce = XNonce::from_slice(&header[21 + SALT_LEN..]);
+
+    let key = derive_key(passphrase, salt, (param(0), param(1), param(2)))?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let payload = Payload {
+        msg: encrypted,
+        aad: header,
+    };
+    let plain = cipher.decrypt(nonce, payload).map_err(|_| {
+        AppError::Validation(
+            "Oops... failed to decrypt the vault. The passphrase may be wrong..".to_string(),
+        )
+    })?;
+    Ok(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テストでは鍵導出の負荷を下げる
+    const TEST_PARAMS: (u32, u32, u32) = (64, 1, 1);
+    const PLAIN: &[u8] = b"[a-awsst]\naws_access_key_id = AKIA\naws_secret_access_key = secret\n";
+
+    fn encrypted() -> Vec<u8> {
+        encrypt("passphrase", PLAIN, TEST_PARAMS).unwrap()
+    }
+
+    /// 復号に失敗した場合のメッセージ
+    fn decrypt_error(passphrase: &str, data: &[u8]) -> String {
+        decrypt(passphrase, data).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let data = encrypted();
+        assert_eq!(&data[..8], MAGIC);
+        assert_eq!(data[8], FORMAT_VERSION);
+        assert_eq!(decrypt("passphrase", &data).unwrap(), PLAIN);
+        // 同じ内容でもソルトとナンスが異なるため暗号文は一致しない
+        assert_ne!(encrypted(), data);
+    }
+
+    #[test]
+    fn decrypt_rejects_wrong_passphrase() {
+        assert!(decrypt_error("wrong", &encrypted()).contains("failed to decrypt"));
+    }
+
+    #[test]
+    fn decrypt_rejects_tampered_data() {
+        // ヘッダ(ナンス)の改ざんは認証データの検証で検知する
+        let mut data = encrypted();
+        data[HEADER_LEN - 1] ^= 0x01;
+        assert!(decrypt_error("passphrase", &data).contains("failed to decrypt"));
+
+        let mut data = encrypted();
+        data[HEADER_LEN] ^= 0x01;
+        assert!(decrypt_error("passphrase", &data).contains("failed to decrypt"));
+    }
+
+    #[test]
+    fn decrypt_rejects_invalid_header() {
+        let mut data = encrypted();
+        data[0] = b'X';
+        assert!(decrypt_error("passphrase", &data).contains("broken"));
+
+        let mut data = encrypted();
+        data[8] = FORMAT_VERSION + 1;
+        assert!(decrypt_error("passphrase", &data).contains("unsupported vault version"));
+
+        let data = encrypted();
+        assert!(decrypt_error("passphrase", &data[..HEADER_LEN - 1]).contains("broken"));
+        // ヘッダのみで暗号文が途中で切れている場合も復号できない
+        assert!(decrypt_error("passphrase", &data[..data.len() - 1]).contains("failed to decrypt"));
+    }
+}
@@ -0,0 +1,457 @@
+use aws_sdk_ssooidc::operation::create_token::CreateTokenError;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use sha1::{Digest, Sha1};
+
+use super::error::AppError;
+use super::utils::{self, prompt::Prompter};
+
+/// デバイス認証でトークンを取得する際のグラントタイプ
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// リフレッシュトークンでトークンを更新する際のグラントタイプ
+const REFRESH_TOKEN_GRANT_TYPE: &str = "refresh_token";
+/// クライアントを登録する際の名称
+const CLIENT_NAME: &str = "awsst";
+/// トークンのキャッシュを保存するディレクトリ
+const CACHE_DIR: &str = "sso/cache";
+/// 期限切れとして扱うアクセストークンの残り時間(秒)
+/// AWS CLIと同様に15分前から更新する
+const TOKEN_REFRESH_BEFORE: i64 = 15 * 60;
+
+/// IAM Identity Center(SSO)のプロファイルの設定
+#[derive(Debug, Clone)]
+pub struct SsoConfig {
+    // `[sso-session name]`の名称. 未設定の場合は旧形式の設定
+    pub session_name: Option<String>,
+    pub start_url: String,
+    pub region: String,
+    pub registration_scopes: Vec<String>,
+    pub account_id: String,
+    pub role_name: String,
+    // ローカルのスタブなどを利用する場合のエンドポイント
+    pub endpoint_url: Option<String>,
+}
+
+/// `~/.aws/sso/cache`に保存するトークン
+/// AWS CLIやSDKと共有するため同じ形式で保存する
+#[derive(Debug, Clone)]
+struct TokenCache {
+    start_url: String,
+    region: String,
+    access_token: String,
+    expires_at: DateTime<Utc>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    registration_expires_at: Option<DateTime<Utc>>,
+    refresh_token: Option<String>,
+}
+
+impl TokenCache {
+    /// キャッシュのファイル名
+    /// AWS CLIと同様に`sso_session`または開始URLのSHA-1をファイル名とする
+    fn file_name(sso: &SsoConfig) -> String {
+        let key = sso.session_name.as_ref().unwrap_or(&sso.start_url);
+        let hash = Sha1::digest(key.as_bytes())
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect::<String>();
+        format!("{}/{}.json", CACHE_DIR, hash)
+    }
+
+    /// キャッシュを読み込む
+    /// 存在しない場合や形式が不正な場合は`None`
+    fn load(sso: &SsoConfig) -> Option<TokenCache> {
+        let path = utils::file::aws_dir().ok()?.join(Self::file_name(sso));
+        let text = std::fs::read_to_string(path).ok()?;
+        let json = serde_json::from_str::<serde_json::Value>(&text).ok()?;
+        let string = |key: &str| {
+            json.get(key)
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string())
+        };
+        let datetime = |key: &str| {
+            DateTime::parse_from_rfc3339(&string(key)?)
+                .ok()
+                .map(|x| x.with_timezone(&Utc))
+        };
+
+        Some(TokenCache {
+            start_url: string("startUrl")?,
+            region: string("region").unwrap_or_else(|| sso.region.clone()),
+            access_token: string("accessToken")?,
+            expires_at: datetime("expiresAt")?,
+            client_id: string("clientId"),
+            client_secret: string("clientSecret"),
+            registration_expires_at: datetime("registrationExpiresAt"),
+            refresh_token: string("refreshToken"),
+        })
+    }
+
+    /// キャッシュを書き込む
+    fn save(
+        &self,
+        sso: &SsoConfig,
+        prompter: &mut Prompter,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file_name = Self::file_name(sso);
+        // `--dry-run`の場合は書き込まない
+        if utils::file::is_dry_run() {
+            prompter.keyvalue("Would write SSO token cache", &file_name);
+            return Ok(());
+        }
+
+        let format = |x: &DateTime<Utc>| x.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let mut json = serde_json::json!({
+            "startUrl": self.start_url,
+            "region": self.region,
+            "accessToken": self.access_token,
+            "expiresAt": format(&self.expires_at),
+        });
+        let optionals = [
+            ("clientId", self.client_id.clone()),
+            ("clientSecret", self.client_secret.clone()),
+            (
+                "registrationExpiresAt",
+                self.registration_expires_at.as_ref().map(format),
+            ),
+            ("refreshToken", self.refresh_token.clone()),
+        ];
+        for (key, val) in optionals {
+            if let Some(val) = val {
+                json[key] = serde_json::Value::String(val);
+            }
+        }
+        utils::file::write_bytes(&file_name, json.to_string().as_bytes(), false)
+    }
+
+    /// アクセストークンが期限内か確認
+    fn is_valid(&self) -> bool {
+        self.expires_at - Utc::now() > Duration::seconds(TOKEN_REFRESH_BEFORE)
+    }
+
+    /// 登録済みのクライアントが期限内であれば返却
+    fn client(&self) -> Option<Client> {
+        match (
+            &self.client_id,
+            &self.client_secret,
+            self.registration_expires_at,
+        ) {
+            (Some(id), Some(secret), Some(expires_at)) if expires_at > Utc::now() => Some(Client {
+                id: id.clone(),
+                secret: secret.clone(),
+                expires_at,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// OIDCに登録したクライアント
+struct Client {
+    id: String,
+    secret: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// SSOのロールの認証情報を取得
+/// アクセストークンが期限切れの場合はリフレッシュトークンで更新し、更新できない場合はデバイス認証でログインする
+pub async fn sso_role_credentials(
+    sso: &SsoConfig,
+    prompter: &mut Prompter,
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
+    let token = access_token(sso, prompter, false).await?;
+    let result = match get_role_credentials(sso, token).await {
+        // アクセストークンが無効になっている場合はログインし直す
+        Err(aws_sdk_sso::Error::UnauthorizedException(_)) => {
+            let token = access_token(sso, prompter, true).await?;
+            get_role_credentials(sso, token).await?
+        }
+        result => result?,
+    };
+
+    let Some(role) = result else {
+        return Err(Box::new(AppError::Sts(
+            "Oops... SSO did not return role credentials..".to_string(),
+        )));
+    };
+    let cred = aws_sdk_sts::types::Credentials::builder()
+        .access_key_id(role.access_key_id.unwrap_or_default())
+        .secret_access_key(role.secret_access_key.unwrap_or_default())
+        .session_token(role.session_token.unwrap_or_default())
+        .expiration(aws_sdk_sts::primitives::DateTime::from_millis(
+            role.expiration,
+        ))
+        .build()?;
+    Ok(cred)
+}
+
+/// ロールの認証情報を取得
+async fn get_role_credentials(
+    sso: &SsoConfig,
+    token: String,
+) -> Result<Option<aws_sdk_sso::types::RoleCredentials>, aws_sdk_sso::Error> {
+    let mut builder = aws_sdk_sso::Config::builder()
+        .behavior_version(aws_sdk_sso::config::BehaviorVersion::latest())
+        .region(aws_sdk_sso::config::Region::new(sso.region.clone()));
+    if let Some(url) = &sso.endpoint_url {
+        builder = builder.endpoint_url(url);
+    }
+    let client = aws_sdk_sso::Client::from_conf(builder.build());
+
+    let result = client
+        .get_role_credentials()
+        .access_token(token)
+        .account_id(&sso.account_id)
+        .role_name(&sso.role_name)
+        .send()
+        .await
+        .map_err(aws_sdk_sso::Error::from)?;
+    Ok(result.role_credentials)
+}
+
+/// アクセストークンを取得
+/// `login`が`true`の場合はキャッシュを利用せずにログインする
+async fn access_token(
+    sso: &SsoConfig,
+    prompter: &mut Prompter,
+    login: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // 開始URLが変更されている場合はキャッシュを利用しない
+    let cache = TokenCache::load(sso).filter(|x| x.start_url == sso.start_url);
+    let client = oidc_client(sso);
+
+    if let (Some(cache), false) = (&cache, login) {
+        if cache.is_valid() {
+            return Ok(cache.access_token.clone());
+        }
+        // リフレッシュトークンで更新
+        if let (Some(refresh_token), Some(registered)) = (&cache.refresh_token, cache.client()) {
+            match refresh(&client, sso, registered, refresh_token).await {
+                Ok(token) => {
+                    token.save(sso, prompter)?;
+                    return Ok(token.access_token);
+                }
+                Err(_) => prompter.warning("Failed to refresh the SSO token. Please log in again."),
+            }
+        }
+    }
+
+    // 登録済みのクライアントが期限内の場合は再利用する
+    let registered = match cache.and_then(|x| x.client()) {
+        Some(registered) => registered,
+        None => register_client(&client, sso).await?,
+    };
+    let token = device_login(&client, sso, registered, prompter).await?;
+    token.save(sso, prompter)?;
+    Ok(token.access_token)
+}
+
+/// OIDCのクライアント生成
+/// リクエストに署名は不要なため、認証情報は設定しない
+fn oidc_client(sso: &SsoConfig) -> aws_sdk_ssooidc::Client {
+    let mut builder = aws_sdk_ssooidc::Config::builder()
+        .behavior_version(aws_sdk_ssooidc::config::BehaviorVersion::latest())
+        .region(aws_sdk_ssooidc::config::Region::new(sso.region.clone()));
+    if let Some(url) = &sso.endpoint_url {
+        builder = builder.endpoint_url(url);
+    }
+    aws_sdk_ssooidc::Client::from_conf(builder.build())
+}
+
+/// OIDCにクライアントを登録
+async fn register_client(
+    client: &aws_sdk_ssooidc::Client,
+    sso: &SsoConfig,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let scopes = Some(sso.registration_scopes.clone()).filter(|x| !x.is_empty());
+    let result = client
+        .register_client()
+        .client_name(CLIENT_NAME)
+        .client_type("public")
+        .set_scopes(scopes)
+        .send()
+        .await
+        .map_err(aws_sdk_ssooidc::Error::from)?;
+
+    Ok(Client {
+        id: result.client_id.unwrap_or_default(),
+        secret: result.client_secret.unwrap_or_default(),
+        expires_at: DateTime::from_timestamp(result.client_secret_expires_at, 0)
+            .unwrap_or_else(Utc::now),
+    })
+}
+
+/// リフレッシュトークンでアクセストークンを更新
+async fn refresh(
+    client: &aws_sdk_ssooidc::Client,
+    sso: &SsoConfig,
+    registered: Client,
+    refresh_token: &str,
+) -> Result<TokenCache, Box<dyn std::error::Error>> {
+    let result = client
+        .create_token()
+        .client_id(&registered.id)
+        .client_secret(&registered.secret)
+        .grant_type(REFRESH_TOKEN_GRANT_TYPE)
+        .refresh_token(refresh_token)
+        .send()
+        .await
+        .map_err(aws_sdk_ssooidc::Error::from)?;
+
+    Ok(new_token(
+        sso,
+        registered,
+        result.access_token,
+        result.expires_in,
+        result.refresh_token.or(Some(refresh_token.to_string())),
+    ))
+}
+
+/// デバイス認証でログインし、アクセストークンを取得
+/// ブラウザで認証が完了するまでトークンの取得を繰り返す
+async fn device_login(
+    client: &aws_sdk_ssooidc::Client,
+    sso: &SsoConfig,
+    registered: Client,
+    prompter: &mut Prompter,
+) -> Result<TokenCache, Box<dyn std::error::Error>> {
+    let auth = client
+        .start_device_authorization()
+        .client_id(&registered.id)
+        .client_secret(&registered.secret)
+        .start_url(&sso.start_url)
+        .send()
+        .await
+        .map_err(aws_sdk_ssooidc::Error::from)?;
+
+    // 標準出力は`eval`されるため、認証用のURLとコードは標準エラー出力に表示する
+    prompter
+        .standard("Open the following URL in your browser and confirm the code to sign in to SSO.");
+    let url = auth
+        .verification_uri_complete
+        .or(auth.verification_uri)
+        .unwrap_or_default();
+    prompter.keyvalue("URL", &url);
+    prompter.keyvalue("Code", &auth.user_code.unwrap_or_default());
+
+    let device_code = auth.device_code.unwrap_or_default();
+    let mut interval = auth.interval.max(1) as u64;
+    let deadline = Utc::now() + Duration::seconds(auth.expires_in.into());
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        if Utc::now() > deadline {
+            return Err(Box::new(AppError::Sts(
+                "Oops... the SSO sign-in was not completed in time..".to_string(),
+            )));
+        }
+
+        let result = client
+            .create_token()
+            .client_id(&registered.id)
+            .client_secret(&registered.secret)
+            .grant_type(DEVICE_CODE_GRANT_TYPE)
+            .device_code(&device_code)
+            .send()
+            .await;
+        match result.map_err(|e| e.into_service_error()) {
+            Ok(result) => {
+                return Ok(new_token(
+                    sso,
+                    registered,
+                    result.access_token,
+                    result.expires_in,
+                    result.refresh_token,
+                ))
+            }
+            // 認証待ちの場合は待ってから再度取得する
+            Err(CreateTokenError::AuthorizationPendingException(_)) => continue,
+            Err(CreateTokenError::SlowDownException(_)) => interval += 5,
+            Err(e) => return Err(Box::new(aws_sdk_ssooidc::Error::from(e))),
+        }
+    }
+}
+
+/// 取得したアクセストークンからキャッシュを生成
+fn new_token(
+    sso: &SsoConfig,
+    registered: Client,
+    access_token: Option<String>,
+    expires_in: i32,
+    refresh_token: Option<String>,
+) -> TokenCache {
+    TokenCache {
+        start_url: sso.start_url.clone(),
+        region: sso.region.clone(),
+        access_token: access_token.unwrap_or_default(),
+        expires_at: Utc::now() + Duration::seconds(expires_in.into()),
+        client_id: Some(registered.id),
+        client_secret: Some(registered.secret),
+        registration_expires_at: Some(registered.expires_at),
+        refresh_token,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sso_config(session_name: Option<&str>) -> SsoConfig {
+        SsoConfig {
+            session_name: session_name.map(|x| x.to_string()),
+            start_url: "https://my-sso-portal.awsapps.com/start".to_string(),
+            region: "us-east-1".to_string(),
+            registration_scopes: vec![],
+            account_id: "123456789012".to_string(),
+            role_name: "ReadOnly".to_string(),
+            endpoint_url: None,
+        }
+    }
+
+    fn token_cache(expires_in: i64) -> TokenCache {
+        TokenCache {
+            start_url: "https://my-sso-portal.awsapps.com/start".to_string(),
+            region: "us-east-1".to_string(),
+            access_token: "token".to_string(),
+            expires_at: Utc::now() + Duration::seconds(expires_in),
+            client_id: Some("id".to_string()),
+            client_secret: Some("secret".to_string()),
+            registration_expires_at: Some(Utc::now() + Duration::days(1)),
+            refresh_token: None,
+        }
+    }
+
+    #[test]
+    fn file_name_uses_sha1_of_session_name() {
+        assert_eq!(
+            TokenCache::file_name(&sso_config(Some("my-sso"))),
+            "sso/cache/0ad374308c5a4e22f723adf10145eafad7c4031c.json"
+        );
+    }
+
+    #[test]
+    fn file_name_uses_sha1_of_start_url_for_legacy_profiles() {
+        assert_eq!(
+            TokenCache::file_name(&sso_config(None)),
+            "sso/cache/c7aaaf71fcc8777ae2475525ed049d39fe16c484.json"
+        );
+    }
+
+    #[test]
+    fn token_is_refreshed_within_15_minutes_of_expiry() {
+        assert!(token_cache(TOKEN_REFRESH_BEFORE + 60).is_valid());
+        assert!(!token_cache(TOKEN_REFRESH_BEFORE - 60).is_valid());
+        assert!(!token_cache(-60).is_valid());
+    }
+
+    #[test]
+    fn client_is_reused_only_before_registration_expires() {
+        let mut cache = token_cache(3600);
+        assert!(cache.client().is_some());
+
+        cache.registration_expires_at = Some(Utc::now() - Duration::seconds(1));
+        assert!(cache.client().is_none());
+
+        cache.registration_expires_at = Some(Utc::now() + Duration::days(1));
+        cache.client_secret = None;
+        assert!(cache.client().is_none());
+    }
+}
@@ -1,146 +1,1356 @@
+use aws_sdk_sts::{
+    config::Credentials,
+    error::ProvideErrorMetadata,
+    types::{PolicyDescriptorType, Tag},
+};
+use chrono::Utc;
 use dialoguer::{theme::ColorfulTheme, Input};
 use regex::Regex;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::Duration;
 
+use super::error::AppError;
 use super::profile;
+use super::utils;
+
+/// GetSessionTokenで指定できる有効期間(秒)
+const SESSION_TOKEN_DURATION: (i32, i32) = (900, 129600);
+/// AssumeRoleで指定できる有効期間(秒)
+/// ロールの最大セッション時間の上限である12時間までとし、それ以上はロール側の設定で判定される
+const ASSUME_ROLE_DURATION: (i32, i32) = (900, 43200);
+/// 一時的な認証情報でAssumeRoleを行う(ロールの連鎖)場合の有効期間の上限(秒)
+const CHAINED_ROLE_MAX_DURATION: i32 = 3600;
+/// 有効期間が未指定の場合のGetSessionTokenの有効期間(秒)
+const DEFAULT_SESSION_TOKEN_DURATION: i32 = 43200;
+/// 有効期間が未指定の場合のAssumeRoleの有効期間(秒)
+const DEFAULT_ASSUME_ROLE_DURATION: i32 = 3600;
+/// AssumeRoleのセッション名に指定できる長さ
+const ROLE_SESSION_NAME_LENGTH: (usize, usize) = (2, 64);
+/// SDKに渡す認証情報の提供元の名称
+const CREDENTIALS_PROVIDER_NAME: &str = "awsst";
+/// STSのグローバルエンドポイント
+const GLOBAL_ENDPOINT_URL: &str = "https://sts.amazonaws.com";
+/// グローバルエンドポイントへのリクエストに署名するリージョン
+const GLOBAL_SIGNING_REGION: &str = "us-east-1";
+/// `sts_regional_endpoints = legacy`の場合にグローバルエンドポイントを利用するリージョン
+const LEGACY_GLOBAL_REGIONS: [&str; 16] = [
+    "aws-global",
+    "ap-northeast-1",
+    "ap-south-1",
+    "ap-southeast-1",
+    "ap-southeast-2",
+    "ca-central-1",
+    "eu-central-1",
+    "eu-north-1",
+    "eu-west-1",
+    "eu-west-2",
+    "eu-west-3",
+    "sa-east-1",
+    "us-east-1",
+    "us-east-2",
+    "us-west-1",
+    "us-west-2",
+];
+/// MFAのコードの桁数
+const TOKEN_CODE_LENGTH: usize = 6;
+/// MFAのコードが受け付けられてから、同じコードの再利用として扱う秒数(TOTPの2ステップ分)
+const MFA_REUSE_WINDOW: i64 = 60;
+
+/// `--token-code`で指定されたMFAのコード
+/// ロールの連鎖で複数回MFAが必要になる場合もあるため、最初のリクエストでのみ利用する
+static TOKEN_CODE: Mutex<Option<String>> = Mutex::new(None);
 
 pub struct MFAInfo {
     arn: Option<String>,
     code: Option<String>,
+    // 保存したシードから生成したコードの場合は`true`
+    generated: bool,
+}
+
+/// STSのエラーを対処方法を含むメッセージに変換するための情報
+struct Failure<'a> {
+    // 認証情報を取得するプロファイル
+    profile: &'a str,
+    // リクエストに署名したプロファイル(`source_profile`の場合は連鎖元)
+    caller: &'a str,
+    // 署名した認証情報がセッションの場合は`true`
+    caller_session: bool,
+    // OIDCトークンやSAMLアサーションの名称(それらでAssumeRoleする場合のみ)
+    token: Option<&'static str>,
+    role_arn: Option<&'a str>,
+    mfa_serial: Option<&'a str>,
+    mfa_generated: bool,
+    // 直前に受け付けられたMFAのコードと同じステップ内の場合は`true`
+    mfa_reused: bool,
+    region: Option<&'a str>,
+    duration: i32,
 }
 
 pub struct AssumeRoleReq {
     arn: Option<String>,
     session_name: String,
+    duration: i32,
+    options: RoleOptions,
+}
+
+/// AssumeRoleの任意のパラメータ
+/// `config`のプロファイルごとの設定と`session`の引数で指定する
+#[derive(Debug, Clone, Default)]
+pub struct RoleOptions {
+    pub external_id: Option<String>,
+    pub source_identity: Option<String>,
+    pub tags: Vec<(String, String)>,
+    pub transitive_tag_keys: Vec<String>,
+    // インラインのセッションポリシー(JSON)
+    pub policy: Option<String>,
+    pub policy_arns: Vec<String>,
+}
+
+impl RoleOptions {
+    /// いずれのパラメータも指定されていないか確認
+    pub fn is_empty(&self) -> bool {
+        self.external_id.is_none()
+            && self.source_identity.is_none()
+            && self.tags.is_empty()
+            && self.transitive_tag_keys.is_empty()
+            && self.policy.is_none()
+            && self.policy_arns.is_empty()
+    }
+
+    /// AssumeRoleでのみ指定できるパラメータが指定されている場合はその名称を返却
+    /// AssumeRoleWithWebIdentityとAssumeRoleWithSAMLではセッションタグなどはIdPが指定する
+    pub fn assume_role_only(&self) -> Option<&'static str> {
+        if self.external_id.is_some() {
+            Some("external_id")
+        } else if self.source_identity.is_some() {
+            Some("source_identity")
+        } else if !self.tags.is_empty() || !self.transitive_tag_keys.is_empty() {
+            Some("session tags")
+        } else {
+            None
+        }
+    }
+
+    /// `other`で指定されているパラメータで上書きする
+    pub fn overridden(self, other: &RoleOptions) -> RoleOptions {
+        let list = |base: Vec<String>, val: &Vec<String>| {
+            if val.is_empty() {
+                base
+            } else {
+                val.clone()
+            }
+        };
+        RoleOptions {
+            external_id: other.external_id.clone().or(self.external_id),
+            source_identity: other.source_identity.clone().or(self.source_identity),
+            tags: if other.tags.is_empty() {
+                self.tags
+            } else {
+                other.tags.clone()
+            },
+            transitive_tag_keys: list(self.transitive_tag_keys, &other.transitive_tag_keys),
+            policy: other.policy.clone().or(self.policy),
+            policy_arns: list(self.policy_arns, &other.policy_arns),
+        }
+    }
+}
+
+/// STSのエンドポイントの設定
+/// `config`のプロファイルごとの`endpoint_url`、`use_fips_endpoint`、`sts_regional_endpoints`で指定する
+#[derive(Debug, Clone, Default)]
+pub struct StsEndpoint {
+    pub endpoint_url: Option<String>,
+    pub use_fips: bool,
+    // `sts_regional_endpoints = legacy`の場合は一部のリージョンでグローバルエンドポイントを利用する
+    pub legacy: bool,
+}
+
+impl StsEndpoint {
+    /// リクエストに利用するエンドポイントのURLと署名するリージョンを取得
+    /// URLが`None`の場合はリージョンごとのエンドポイントを利用する
+    pub fn resolve(&self, region: Option<&str>) -> (Option<String>, Option<String>) {
+        if let Some(url) = &self.endpoint_url {
+            return (Some(url.clone()), region.map(|x| x.to_string()));
+        }
+        if self.legacy
+            && !self.use_fips
+            && region.is_some_and(|x| LEGACY_GLOBAL_REGIONS.contains(&x))
+        {
+            // グローバルエンドポイントはus-east-1で署名する
+            return (
+                Some(GLOBAL_ENDPOINT_URL.to_string()),
+                Some(GLOBAL_SIGNING_REGION.to_string()),
+            );
+        }
+        (None, region.map(|x| x.to_string()))
+    }
+}
+
+/// OIDCトークンでロールを引き受けるための設定
+/// `config`のプロファイルごとの`role_arn`と`web_identity_token_file`または`web_identity_token_command`で指定する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebIdentity {
+    pub role_arn: String,
+    pub token: WebIdentityToken,
+}
+
+/// OIDCトークンの取得元
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebIdentityToken {
+    // トークンを保存したファイル
+    File(String),
+    // トークンを標準出力に出力するコマンド
+    Command(String),
+}
+
+/// IAMロールのARN
+/// `aws`以外のパーティション(`aws-cn`、`aws-us-gov`など)も扱う
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleArn {
+    pub partition: String,
+    pub account_id: String,
+    // パスを除いたロール名
+    pub name: String,
+}
+
+impl RoleArn {
+    /// `arn:<partition>:iam::<account_id>:role/<path/name>`の形式のARNを変換
+    /// 形式が異なる場合は`None`
+    pub fn parse(arn: &str) -> Option<RoleArn> {
+        let re_arn = Regex::new(r"^arn:([a-z][a-z0-9-]*):iam::([0-9]{12}):role/(.+)$").unwrap();
+        let caps = re_arn.captures(arn.trim())?;
+        let name = caps[3].rsplit('/').next().filter(|x| !x.is_empty())?;
+        Some(RoleArn {
+            partition: caps[1].to_string(),
+            account_id: caps[2].to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+/// `Key=Value`の形式のセッションタグを変換
+pub fn parse_tag(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, val)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), val.trim().to_string()))
+        }
+        _ => Err(format!("invalid tag [{}]. Use the form `Key=Value`", text)),
+    }
+}
+
+/// セッションポリシーを取得
+/// `file://`で始まる場合はファイルから読み込む
+pub fn read_policy(text: &str) -> Result<String, String> {
+    match text.strip_prefix("file://") {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read the policy file [{}]: {}", path, e)),
+        None => Ok(text.to_string()),
+    }
 }
 
 /// AWSへSTSリクエスト
+/// `duration`が未指定の場合はプロファイルの`duration_seconds`を利用する
+/// `source`が指定されている場合は`source_profile`のセッションでAssumeRoleを行う
+/// `options`はプロファイルの設定より優先するAssumeRoleのパラメータ
 pub async fn aws_sts_request(
     config: &profile::configs::Config,
     credential: profile::credentials::Credential,
-) -> Result<aws_sdk_sts::types::Credentials, aws_sdk_sts::Error> {
-    // MFA情報取得
-    let mfa = get_mfa_info(credential.mfa_serial.clone());
-
-    // MFAが設定済みだが、コードが入力されていない場合は終了
-    if credential.mfa_serial.is_some() && mfa.code.is_none() {
-        panic!("No code entered");
-    }
+    duration: Option<i32>,
+    source: Option<&profile::credentials::Credential>,
+    options: &RoleOptions,
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
+    // 有効期間を取得
+    let duration = match duration {
+        Some(duration) => Some(duration),
+        None => config.duration_seconds().map_err(AppError::Parse)?,
+    };
 
     // AssumeRoleが指定されている場合
-    if let Some(assule_role) = credential.role_arn {
-        let re_session_name = Regex::new(r"arn:aws:iam::[0-9]*:role/(.*)").unwrap();
-        let session_name = match re_session_name.captures(assule_role.as_str()) {
-            Some(data) => data.get(1).unwrap().as_str().to_string(),
-            None => assule_role.clone(),
+    if let Some(assule_role) = credential.role_arn.clone() {
+        let Some(role_arn) = RoleArn::parse(&assule_role) else {
+            return Err(Box::new(AppError::Validation(format!(
+                "Oops... role_arn [{}] of profile [{}] is not an IAM role ARN such as `arn:aws:iam::123456789012:role/name`..",
+                assule_role,
+                profile::store::profile_name(&credential.name)
+            ))));
         };
+        let session_name = role_session_name(config, &role_arn)?;
+
+        let duration = validate_duration(
+            duration.unwrap_or(DEFAULT_ASSUME_ROLE_DURATION),
+            assume_role_duration(source.is_some_and(|x| x.assumed_role)),
+            "AssumeRole",
+        )?;
 
         let role_req = AssumeRoleReq {
             arn: Some(assule_role),
             session_name,
+            duration,
+            options: config
+                .role_options()
+                .map_err(AppError::Parse)?
+                .overridden(options),
         };
 
-        return sts_assume_role(config, role_req, mfa).await;
+        let mfa = mfa_info(config, &credential).await?;
+        return sts_assume_role(config, source.unwrap_or(&credential), role_req, mfa).await;
+    }
+
+    // `source_profile`はAssumeRoleでのみ利用できる
+    if credential.source_profile.is_some() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] has source_profile but no role_arn..",
+            profile::store::profile_name(&credential.name)
+        ))));
+    }
+
+    // AssumeRoleのパラメータが指定されている場合は無視せずに通知する
+    if !options.is_empty() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... AssumeRole options are given but profile [{}] has no role_arn..",
+            profile::store::profile_name(&credential.name)
+        ))));
+    }
+
+    // アクセスキーがない場合はSDKのデフォルトの認証情報を利用しないよう通知する
+    if credential.access_key_id.is_none() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... profile [{}] has no access key..",
+            profile::store::profile_name(&credential.name)
+        ))));
     }
 
     // 通常のセッショントークンを取得
-    sts_session_token(config, mfa).await
+    let duration = validate_duration(
+        duration.unwrap_or(DEFAULT_SESSION_TOKEN_DURATION),
+        SESSION_TOKEN_DURATION,
+        "GetSessionToken",
+    )?;
+    let mfa = mfa_info(config, &credential).await?;
+    sts_session_token(config, &credential, mfa, duration).await
 }
 
-/// MFA情報を取得
-///
-fn get_mfa_info(mfa_serial: Option<String>) -> MFAInfo {
-    // 指定なしの場合はNoneで生成
-    if mfa_serial.is_none() {
-        return MFAInfo {
+/// AssumeRoleのセッション名を生成
+/// `role_session_name`のテンプレートのプレースホルダーを置換し、STSで利用できる形式に変換する
+/// 未設定の場合はロール名を利用する
+fn role_session_name(
+    config: &profile::configs::Config,
+    role_arn: &RoleArn,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let template = config
+        .role_session_name()
+        .map(|x| x.as_str())
+        .unwrap_or("{role}");
+
+    let re_placeholder = Regex::new(r"\{([^{}]*)\}").unwrap();
+    let mut unknown = None;
+    let name = re_placeholder.replace_all(template, |caps: &regex::Captures| match &caps[1] {
+        "user" => whoami::fallible::username().unwrap_or_default(),
+        "hostname" => whoami::fallible::hostname().unwrap_or_default(),
+        "profile" => config.name.clone(),
+        "role" => role_arn.name.clone(),
+        "timestamp" => Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+        other => {
+            unknown = Some(other.to_string());
+            String::new()
+        }
+    });
+    if let Some(placeholder) = unknown {
+        return Err(Box::new(AppError::Parse(format!(
+            "Oops... unknown placeholder {{{}}} in {} of profile [{}]. Use {{user}}, {{hostname}}, {{profile}}, {{role}} or {{timestamp}}..",
+            placeholder,
+            profile::configs::ROLE_SESSION_NAME_KEY,
+            config.name
+        ))));
+    }
+
+    Ok(sanitize_session_name(&name))
+}
+
+/// STSで利用できるセッション名に変換
+/// 利用できない文字は`-`に置換し、長さを2文字から64文字の範囲に収める
+fn sanitize_session_name(name: &str) -> String {
+    let mut name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "_+=,.@-".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(ROLE_SESSION_NAME_LENGTH.1)
+        .collect::<String>();
+    if name.len() < ROLE_SESSION_NAME_LENGTH.0 {
+        name.push_str("-awsst");
+    }
+    name
+}
+
+/// AssumeRoleWithWebIdentityで利用するOIDCトークンを取得
+fn web_identity_token(
+    config: &profile::configs::Config,
+    web_identity: &WebIdentity,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let name = config.name.as_str();
+    let token = match &web_identity.token {
+        WebIdentityToken::File(path) => std::fs::read_to_string(utils::file::expand_home(path))
+            .map_err(|e| {
+                format!(
+                    "Oops... cannot read web_identity_token_file [{}] of profile [{}]: {}..",
+                    path, name, e
+                )
+            }),
+        WebIdentityToken::Command(command) => {
+            utils::command::run(command, name, None).map_err(|e| {
+                format!(
+                    "Oops... web_identity_token_command failed for profile [{}]: `{}`\n{}",
+                    name, command, e
+                )
+            })
+        }
+    }
+    .map_err(AppError::File)?;
+
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... the web identity token of profile [{}] is empty..",
+            name
+        ))));
+    }
+    Ok(token.to_string())
+}
+
+/// `--token-code`で指定されたMFAのコードを設定
+/// `-`の場合は標準入力から読み込む
+pub fn set_token_code(arg: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let code = if arg == "-" {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        arg.to_string()
+    };
+    if let Ok(mut token_code) = TOKEN_CODE.lock() {
+        *token_code = Some(code.trim().to_string());
+    }
+    Ok(())
+}
+
+/// MFA情報取得
+/// MFAが未設定の場合はいずれも`None`
+/// コードは`--token-code`、保存したシード、`mfa_process`、プロンプトでの入力の順に取得する
+async fn mfa_info(
+    config: &profile::configs::Config,
+    credential: &profile::credentials::Credential,
+) -> Result<MFAInfo, Box<dyn std::error::Error>> {
+    let Some(mfa_serial) = credential.mfa_serial.clone() else {
+        return Ok(MFAInfo {
             arn: None,
             code: None,
-        };
+            generated: false,
+        });
+    };
+    let token_code = TOKEN_CODE.lock().ok().and_then(|mut x| x.take());
+    let name = profile::store::profile_name(&credential.name);
+    let (code, generated) = if let Some(code) = token_code {
+        (
+            validate_token_code(code, "--token-code", &mfa_serial)?,
+            false,
+        )
+    } else if let Some(seed) = &credential.mfa_totp_seed {
+        (totp_code(&mfa_serial, seed, name).await?, true)
+    } else if let Some(command) = config.mfa_process() {
+        let code = utils::command::run(command, name, None).map_err(|e| {
+            AppError::File(format!(
+                "Oops... {} failed for profile [{}]: `{}`\n{}",
+                profile::configs::MFA_PROCESS_KEY,
+                name,
+                command,
+                e
+            ))
+        })?;
+        let code = validate_token_code(
+            code.trim().to_string(),
+            profile::configs::MFA_PROCESS_KEY,
+            &mfa_serial,
+        )?;
+        (code, false)
+    } else {
+        (get_mfa_info(&mfa_serial)?, false)
+    };
+    Ok(MFAInfo {
+        arn: Some(mfa_serial),
+        code: Some(code),
+        generated,
+    })
+}
+
+/// MFAのコードが6桁の数字か確認
+/// 形式の誤りはSTSにリクエストする前に通知する
+fn validate_token_code(
+    code: String,
+    source: &str,
+    mfa_serial: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !is_token_code(&code) {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... the MFA code from {} for device [{}] must be {} digits..",
+            source, mfa_serial, TOKEN_CODE_LENGTH
+        ))));
     }
+    Ok(code)
+}
+
+/// MFAのコードの形式か判定
+fn is_token_code(code: &str) -> bool {
+    code.len() == TOKEN_CODE_LENGTH && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// 保存したシードからMFAのコードを生成
+/// 現在のステップのコードがすでに受け付けられている場合は、再利用で拒否されないよう次のステップまで待つ
+async fn totp_code(
+    mfa_serial: &str,
+    seed: &str,
+    name: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let key = utils::totp::decode_seed(seed).map_err(|e| {
+        AppError::Parse(format!(
+            "Oops... {} of profile [{}]: {}. Enroll it again with `awsst mfa enroll -p {}`..",
+            profile::credentials::MFA_TOTP_SEED_KEY,
+            name,
+            e,
+            name
+        ))
+    })?;
+
+    let now = Utc::now().timestamp();
+    let mut step = utils::totp::step_at(now);
+    if let Some(used) = profile::mfa_last_used(mfa_serial).map(utils::totp::step_at) {
+        if used >= step {
+            step = used + 1;
+            let wait = step * utils::totp::STEP_SECONDS - now;
+            utils::prompt::Prompter::new().standard(&format!(
+                "Waiting {}s for the next MFA code of device [{}]...",
+                wait, mfa_serial
+            ));
+            tokio::time::sleep(Duration::from_secs(wait.unsigned_abs())).await;
+        }
+    }
+    Ok(utils::totp::code(&key, step))
+}
 
+/// AssumeRoleで指定できる有効期間の範囲
+/// ロールで取得した認証情報からのAssumeRoleはロールの連鎖となり、有効期間の上限が1時間になる
+fn assume_role_duration(chained: bool) -> (i32, i32) {
+    if chained {
+        (ASSUME_ROLE_DURATION.0, CHAINED_ROLE_MAX_DURATION)
+    } else {
+        ASSUME_ROLE_DURATION
+    }
+}
+
+/// 有効期間がSTSで指定できる範囲内か確認
+fn validate_duration(
+    duration: i32,
+    range: (i32, i32),
+    operation: &str,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    if duration < range.0 || duration > range.1 {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... duration {}s is out of range for {} ({}s - {}s)..",
+            duration, operation, range.0, range.1
+        ))));
+    }
+    Ok(duration)
+}
+
+/// MFAのコードを入力してもらう
+/// 入力が中断された場合はキャンセルとして扱う
+fn get_mfa_info(mfa_serial: &str) -> Result<String, Box<dyn std::error::Error>> {
     // Get the token code at the prompt for entering standard text
-    let msg = format!(
-        "Enter AWS MFA code for device [{}]",
-        mfa_serial.clone().unwrap()
-    );
-    let input: Option<String> = match Input::<String>::with_theme(&ColorfulTheme::default())
+    let msg = format!("Enter AWS MFA code for device [{}]", mfa_serial);
+    let input = Input::<String>::with_theme(&ColorfulTheme::default())
         .with_prompt(msg)
+        .validate_with(|input: &String| {
+            if is_token_code(input.trim()) {
+                Ok(())
+            } else {
+                Err(format!("The MFA code must be {} digits", TOKEN_CODE_LENGTH))
+            }
+        })
         .interact_text()
-    {
-        Ok(val) => Some(val),
-        Err(_) => None,
-    };
+        .map_err(AppError::from_prompt)?;
+    Ok(input.trim().to_string())
+}
 
-    MFAInfo {
-        arn: mfa_serial,
-        code: input,
-    }
+/// リクエストに利用する認証情報に変換
+/// アクセスキーが設定されていない場合は`None`
+fn caller_credentials(credential: &profile::credentials::Credential) -> Option<Credentials> {
+    Some(Credentials::new(
+        credential.access_key_id.clone()?,
+        credential.secret_access_key.clone()?,
+        credential.session_token.clone(),
+        None,
+        CREDENTIALS_PROVIDER_NAME,
+    ))
 }
 
 /// STSクライアント生成
-///
-async fn __sts_client(config: &profile::configs::Config) -> aws_sdk_sts::Client {
-    let config_builder = aws_config::defaults(aws_config::BehaviorVersion::latest())
-        .region(aws_config::Region::new(config.region.clone()));
+/// `caller`のアクセスキーが設定されている場合はその認証情報でリクエストを行う
+async fn __sts_client(
+    config: &profile::configs::Config,
+    caller: Option<Credentials>,
+) -> Result<aws_sdk_sts::Client, Box<dyn std::error::Error>> {
+    let endpoint = config.sts_endpoint().map_err(AppError::Parse)?;
+
+    let mut config_builder = sts_config_loader(config, &endpoint);
+    if let Some(caller) = caller {
+        config_builder = config_builder.credentials_provider(caller);
+    }
 
     let aws_config = config_builder.load().await;
 
-    aws_sdk_sts::Client::new(&aws_config)
+    Ok(aws_sdk_sts::Client::new(&aws_config))
+}
+
+/// STSクライアントの設定のローダーを生成
+/// 選択中のプロファイル(`AWS_PROFILE`)のエンドポイントやリージョンを引き継がないよう、
+/// SDKが読み込むプロファイルを対象のプロファイルに固定する
+fn sts_config_loader(
+    config: &profile::configs::Config,
+    endpoint: &StsEndpoint,
+) -> aws_config::ConfigLoader {
+    // リージョンが未設定の場合はSDKのデフォルトの解決方法に任せる
+    let mut config_builder = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .profile_name(&config.name)
+        .use_fips(endpoint.use_fips);
+    let (endpoint_url, region) = endpoint.resolve(config.region.as_deref());
+    if let Some(url) = endpoint_url {
+        config_builder = config_builder.endpoint_url(url);
+    }
+    if let Some(region) = region {
+        config_builder = config_builder.region(aws_config::Region::new(region));
+    }
+    config_builder
 }
 
 /// セッショントークンを取得
 ///
 pub async fn sts_session_token(
     config: &profile::configs::Config,
+    caller: &profile::credentials::Credential,
     mfa: MFAInfo,
-) -> Result<aws_sdk_sts::types::Credentials, aws_sdk_sts::Error> {
+    duration: i32,
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
     // クライアント
-    let client = __sts_client(config).await;
+    let client = __sts_client(config, caller_credentials(caller)).await?;
+    let name = profile::store::profile_name(&caller.name);
+    let failure = Failure {
+        profile: name,
+        caller: name,
+        caller_session: caller.session_token.is_some(),
+        token: None,
+        role_arn: None,
+        mfa_serial: mfa.arn.as_deref(),
+        mfa_generated: mfa.generated,
+        mfa_reused: false,
+        region: config.region.as_deref(),
+        duration,
+    };
 
     // リクエスト
     let result = client
         .get_session_token()
-        .set_serial_number(mfa.arn)
-        .set_token_code(mfa.code)
-        .set_duration_seconds(Some(43200))
+        .set_serial_number(mfa.arn.clone())
+        .set_token_code(mfa.code.clone())
+        .set_duration_seconds(Some(duration))
         .send()
-        .await?;
+        .await
+        .map_err(|e| sts_error(aws_sdk_sts::Error::from(e), &failure))?;
+    record_mfa_use(&mfa);
 
     // Credentialを返却
-    Ok(result.credentials.expect("should include credentials"))
+    Ok(result.credentials.ok_or_else(no_credentials)?)
 }
 
 /// Assume Roleを行う
 ///
 pub async fn sts_assume_role(
     config: &profile::configs::Config,
+    caller: &profile::credentials::Credential,
     role: AssumeRoleReq,
     mfa: MFAInfo,
-) -> Result<aws_sdk_sts::types::Credentials, aws_sdk_sts::Error> {
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
     // クライアント
-    let client = __sts_client(config).await;
+    let client = __sts_client(config, caller_credentials(caller)).await?;
+
+    // セッションタグ
+    let mut tags = Vec::<Tag>::new();
+    for (key, val) in role.options.tags {
+        tags.push(Tag::builder().key(key).value(val).build()?);
+    }
+    // 管理ポリシーのARN
+    let policy_arns = role
+        .options
+        .policy_arns
+        .into_iter()
+        .map(|arn| PolicyDescriptorType::builder().arn(arn).build())
+        .collect::<Vec<PolicyDescriptorType>>();
+
+    let failure = Failure {
+        profile: &config.name,
+        caller: profile::store::profile_name(&caller.name),
+        caller_session: caller.session_token.is_some(),
+        token: None,
+        role_arn: role.arn.as_deref(),
+        mfa_serial: mfa.arn.as_deref(),
+        mfa_generated: mfa.generated,
+        mfa_reused: false,
+        region: config.region.as_deref(),
+        duration: role.duration,
+    };
 
     // リクエスト
     let result = client
         .assume_role()
         .role_session_name(role.session_name)
-        .set_role_arn(role.arn)
-        .set_serial_number(mfa.arn)
-        .set_token_code(mfa.code)
-        .set_duration_seconds(Some(3600))
+        .set_role_arn(role.arn.clone())
+        .set_serial_number(mfa.arn.clone())
+        .set_token_code(mfa.code.clone())
+        .set_duration_seconds(Some(role.duration))
+        .set_external_id(role.options.external_id)
+        .set_source_identity(role.options.source_identity)
+        .set_tags(non_empty(tags))
+        .set_transitive_tag_keys(non_empty(role.options.transitive_tag_keys))
+        .set_policy(role.options.policy)
+        .set_policy_arns(non_empty(policy_arns))
+        .send()
+        .await
+        .map_err(|e| sts_error(aws_sdk_sts::Error::from(e), &failure))?;
+    record_mfa_use(&mfa);
+
+    // Credentialを返却
+    Ok(result.credentials.ok_or_else(no_credentials)?)
+}
+
+/// OIDCトークンでAssume Roleを行う
+/// `duration`と`options`はプロファイルの設定より優先する
+/// リクエストに署名は不要なため、呼び出し元の認証情報は利用しない
+pub async fn sts_assume_role_with_web_identity(
+    config: &profile::configs::Config,
+    web_identity: &WebIdentity,
+    duration: Option<i32>,
+    options: &RoleOptions,
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
+    let name = config.name.as_str();
+    let Some(role_arn) = RoleArn::parse(&web_identity.role_arn) else {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... role_arn [{}] of profile [{}] is not an IAM role ARN such as `arn:aws:iam::123456789012:role/name`..",
+            web_identity.role_arn, name
+        ))));
+    };
+    let duration = match duration {
+        Some(duration) => Some(duration),
+        None => config.duration_seconds().map_err(AppError::Parse)?,
+    };
+    let role = AssumeRoleReq {
+        arn: Some(web_identity.role_arn.clone()),
+        session_name: role_session_name(config, &role_arn)?,
+        duration: validate_duration(
+            duration.unwrap_or(DEFAULT_ASSUME_ROLE_DURATION),
+            assume_role_duration(false),
+            "AssumeRoleWithWebIdentity",
+        )?,
+        options: config
+            .role_options()
+            .map_err(AppError::Parse)?
+            .overridden(options),
+    };
+
+    // AssumeRoleWithWebIdentityで指定できないパラメータが指定されている場合は通知する
+    if let Some(key) = role.options.assume_role_only() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... {} cannot be used with the web identity token of profile [{}]..",
+            key, name
+        ))));
+    }
+    let token = web_identity_token(config, web_identity)?;
+
+    // クライアント
+    let client = __sts_client(config, None).await?;
+
+    // 管理ポリシーのARN
+    let policy_arns = role
+        .options
+        .policy_arns
+        .into_iter()
+        .map(|arn| PolicyDescriptorType::builder().arn(arn).build())
+        .collect::<Vec<PolicyDescriptorType>>();
+
+    let failure = Failure {
+        profile: name,
+        caller: name,
+        caller_session: false,
+        token: Some("web identity token"),
+        role_arn: role.arn.as_deref(),
+        mfa_serial: None,
+        mfa_generated: false,
+        mfa_reused: false,
+        region: config.region.as_deref(),
+        duration: role.duration,
+    };
+
+    // リクエスト
+    let result = client
+        .assume_role_with_web_identity()
+        .role_session_name(role.session_name)
+        .set_role_arn(role.arn.clone())
+        .web_identity_token(token)
+        .set_duration_seconds(Some(role.duration))
+        .set_policy(role.options.policy)
+        .set_policy_arns(non_empty(policy_arns))
+        .send()
+        .await
+        .map_err(|e| sts_error(aws_sdk_sts::Error::from(e), &failure))?;
+
+    // Credentialを返却
+    Ok(result.credentials.ok_or_else(no_credentials)?)
+}
+
+/// SAMLアサーションでAssume Roleを行う
+/// `duration`と`options`はプロファイルの設定より優先する
+pub async fn sts_assume_role_with_saml(
+    config: &profile::configs::Config,
+    role_arn: String,
+    principal_arn: String,
+    assertion: String,
+    duration: Option<i32>,
+    options: &RoleOptions,
+) -> Result<aws_sdk_sts::types::Credentials, Box<dyn std::error::Error>> {
+    let duration = match duration {
+        Some(duration) => duration,
+        None => config
+            .duration_seconds()
+            .map_err(AppError::Parse)?
+            .unwrap_or(DEFAULT_ASSUME_ROLE_DURATION),
+    };
+    let duration = validate_duration(duration, ASSUME_ROLE_DURATION, "AssumeRoleWithSAML")?;
+    let options = config
+        .role_options()
+        .map_err(AppError::Parse)?
+        .overridden(options);
+    if let Some(key) = options.assume_role_only() {
+        return Err(Box::new(AppError::Validation(format!(
+            "Oops... {} cannot be used with the SAML assertion of profile [{}]..",
+            key, config.name
+        ))));
+    }
+
+    // クライアント
+    let client = __sts_client(config, None).await?;
+
+    // 管理ポリシーのARN
+    let policy_arns = options
+        .policy_arns
+        .into_iter()
+        .map(|arn| PolicyDescriptorType::builder().arn(arn).build())
+        .collect::<Vec<PolicyDescriptorType>>();
+
+    let failure = Failure {
+        profile: &config.name,
+        caller: &config.name,
+        caller_session: false,
+        token: Some("SAML assertion"),
+        role_arn: Some(&role_arn),
+        mfa_serial: None,
+        mfa_generated: false,
+        mfa_reused: false,
+        region: config.region.as_deref(),
+        duration,
+    };
+
+    // リクエスト
+    let result = client
+        .assume_role_with_saml()
+        .role_arn(role_arn.clone())
+        .principal_arn(principal_arn)
+        .saml_assertion(assertion)
+        .duration_seconds(duration)
+        .set_policy(options.policy)
+        .set_policy_arns(non_empty(policy_arns))
         .send()
-        .await?;
+        .await
+        .map_err(|e| sts_error(aws_sdk_sts::Error::from(e), &failure))?;
 
     // Credentialを返却
-    Ok(result.credentials.expect("should include credentials"))
+    Ok(result.credentials.ok_or_else(no_credentials)?)
+}
+
+/// STSのレスポンスに認証情報が含まれていない場合のエラー
+fn no_credentials() -> AppError {
+    AppError::Sts("Oops... STS did not return credentials..".to_string())
+}
+
+/// MFAのコードが受け付けられた日時を記録
+/// 同じコードの再利用で拒否された場合に、コードの誤りと区別して案内するために利用する
+fn record_mfa_use(mfa: &MFAInfo) {
+    if let (Some(arn), Some(_)) = (&mfa.arn, &mfa.code) {
+        profile::record_mfa_use(arn, Utc::now().timestamp());
+    }
+}
+
+/// STSのエラーを、プロファイル名と解消するためのコマンドを含むメッセージに変換
+/// 対処方法を判断できないエラーはそのまま返す
+fn sts_error(error: aws_sdk_sts::Error, failure: &Failure) -> Box<dyn std::error::Error> {
+    let code = error.code().unwrap_or_default();
+    let message = error.message().unwrap_or_default();
+    // 直前に受け付けられたコードの利用日時は保存しているファイルから取得する
+    let failure = Failure {
+        mfa_reused: failure
+            .mfa_serial
+            .and_then(profile::mfa_last_used)
+            .is_some_and(|used| Utc::now().timestamp() - used < MFA_REUSE_WINDOW),
+        ..*failure
+    };
+    match sts_error_message(code, message, &failure) {
+        Some(text) => Box::new(AppError::Sts(text)),
+        None => Box::new(error),
+    }
+}
+
+/// STSのエラーコードとメッセージから対処方法を含むメッセージを生成
+/// 対処方法を判断できない場合は`None`
+fn sts_error_message(code: &str, message: &str, failure: &Failure) -> Option<String> {
+    let profile = failure.profile;
+    let caller = failure.caller;
+
+    let text = match code {
+        // MFAのコードやデバイスの誤り
+        "AccessDenied" if message.contains("MultiFactorAuthentication") => {
+            mfa_error(message, failure)
+        }
+        // 署名したセッションが無効になっている場合は連鎖元のセッションを取り直す
+        "InvalidClientTokenId" | "SignatureDoesNotMatch" if failure.caller_session => format!(
+            "Oops... the session of profile [{}] is no longer valid ({}). Run `awsst session -p {} -f` and try again..",
+            caller, code, caller
+        ),
+        // 長期的なアクセスキーが削除またはローテーションされている
+        "InvalidClientTokenId" | "SignatureDoesNotMatch" => format!(
+            "Oops... the access key of profile [{}] is invalid ({}). It may have been deleted or rotated; register the current key with `awsst update -p {}`..",
+            caller, code, caller
+        ),
+        "ExpiredToken" | "ExpiredTokenException" => match failure.token {
+            Some(token) => format!(
+                "Oops... the {} of profile [{}] has expired. Get a new one and run `awsst session -p {}` again..",
+                token, profile, profile
+            ),
+            None => format!(
+                "Oops... the session of profile [{}] has expired. Run `awsst session -p {} -f` and try again..",
+                caller, caller
+            ),
+        },
+        // ロールの信頼ポリシーで許可されていない
+        "AccessDenied" if failure.role_arn.is_some() => format!(
+            "Oops... profile [{}] is not allowed to assume role [{}]: {}. Check that the trust policy of the role allows the identity of profile [{}] with the MFA device or external_id it requires, and fix the profile with `awsst update -p {}`..",
+            profile,
+            failure.role_arn.unwrap_or_default(),
+            message,
+            caller,
+            profile
+        ),
+        "RegionDisabledException" => format!(
+            "Oops... STS is not activated in region [{}] for the account of profile [{}]. Activate the region in the IAM account settings, or change the region with `awsst update -p {}`..",
+            failure.region.unwrap_or_default(),
+            profile,
+            profile
+        ),
+        // ロールの最大セッション時間やロールの連鎖の上限を超えている
+        "ValidationError" if message.contains("DurationSeconds exceeds") => format!(
+            "Oops... duration {}s exceeds the maximum session duration of role [{}]. Pass a shorter `--duration` to `awsst session -p {}`, lower `duration_seconds`, or raise the role's maximum session duration..",
+            failure.duration,
+            failure.role_arn.unwrap_or_default(),
+            profile
+        ),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// MFAの認証に失敗した場合のメッセージ
+/// 直前に受け付けられたコードと同じステップ内であれば、コードの再利用として案内する
+fn mfa_error(message: &str, failure: &Failure) -> String {
+    let profile = failure.profile;
+    let serial = failure.mfa_serial.unwrap_or_default();
+    // デバイスのARNがユーザーに紐付いていない
+    if message.contains("serial number") {
+        return format!(
+            "Oops... MFA device [{}] is not associated with the user of profile [{}]. Fix the MFA device ARN with `awsst update -p {}`..",
+            serial, profile, profile
+        );
+    }
+    // 保存したシードから生成したコードは次のステップまで待っているため、再利用ではない
+    if failure.mfa_generated {
+        return format!(
+            "Oops... the MFA code generated from the seed of profile [{}] was rejected. Check the system clock, or enroll the seed again with `awsst mfa enroll -p {}`..",
+            profile, profile
+        );
+    }
+    if failure.mfa_reused {
+        return format!(
+            "Oops... the MFA code for device [{}] was already used. Wait for the next code and run `awsst session -p {}` again..",
+            serial, profile
+        );
+    }
+    format!(
+        "Oops... the MFA code for device [{}] was rejected. Check the code and the clock of the device, then run `awsst session -p {}` again..",
+        serial, profile
+    )
+}
+
+/// 空のリストはパラメータを指定しないよう`None`に変換
+fn non_empty<T>(vec: Vec<T>) -> Option<Vec<T>> {
+    Some(vec).filter(|x| !x.is_empty())
 }
 
 /// Caller Identityを取得
+/// 取得したセッションのアカウントを確認するため、その認証情報でリクエストを行う
 pub async fn caller_identity(
     config: &profile::configs::Config,
-) -> Result<String, aws_sdk_sts::Error> {
+    session: &aws_sdk_sts::types::Credentials,
+) -> Result<String, Box<dyn std::error::Error>> {
     // クライアント
-    let client = __sts_client(config).await;
+    let caller = Credentials::new(
+        session.access_key_id.clone(),
+        session.secret_access_key.clone(),
+        Some(session.session_token.clone()),
+        None,
+        CREDENTIALS_PROVIDER_NAME,
+    );
+    let client = __sts_client(config, Some(caller)).await?;
     // リクエスト
-    let output = client.get_caller_identity().send().await?;
-    Ok(output.account.expect("should include credentials"))
+    let output = client
+        .get_caller_identity()
+        .send()
+        .await
+        .map_err(aws_sdk_sts::Error::from)?;
+    output.account.ok_or_else(|| {
+        Box::new(AppError::Sts(
+            "Oops... STS did not return the account..".to_string(),
+        ))
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_arn_parse_partitions() {
+        for partition in ["aws", "aws-cn", "aws-us-gov"] {
+            let arn = format!("arn:{}:iam::123456789012:role/path/to/Admin", partition);
+            assert_eq!(
+                RoleArn::parse(&arn),
+                Some(RoleArn {
+                    partition: partition.to_string(),
+                    account_id: "123456789012".to_string(),
+                    name: "Admin".to_string(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn role_arn_parse_rejects_other_arns() {
+        assert_eq!(RoleArn::parse("arn:aws:iam::123456789012:user/john"), None);
+        assert_eq!(RoleArn::parse("arn:aws:iam::1234:role/Admin"), None);
+        assert_eq!(RoleArn::parse("arn:aws:iam::123456789012:role/"), None);
+        assert_eq!(RoleArn::parse("Admin"), None);
+    }
+
+    #[test]
+    fn endpoint_resolve_uses_regional_endpoint_by_default() {
+        let endpoint = StsEndpoint::default();
+        assert_eq!(
+            endpoint.resolve(Some("ap-northeast-1")),
+            (None, Some("ap-northeast-1".to_string()))
+        );
+        assert_eq!(endpoint.resolve(None), (None, None));
+    }
+
+    #[test]
+    fn endpoint_resolve_legacy_regions() {
+        let endpoint = StsEndpoint {
+            legacy: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            endpoint.resolve(Some("ap-northeast-1")),
+            (
+                Some(GLOBAL_ENDPOINT_URL.to_string()),
+                Some(GLOBAL_SIGNING_REGION.to_string())
+            )
+        );
+        // 2015年以降に追加されたリージョンはlegacyでも地域エンドポイントを利用する
+        assert_eq!(
+            endpoint.resolve(Some("ap-northeast-3")),
+            (None, Some("ap-northeast-3".to_string()))
+        );
+    }
+
+    #[test]
+    fn endpoint_resolve_fips_skips_global_endpoint() {
+        let endpoint = StsEndpoint {
+            use_fips: true,
+            legacy: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            endpoint.resolve(Some("us-east-1")),
+            (None, Some("us-east-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn endpoint_resolve_prefers_endpoint_url() {
+        let endpoint = StsEndpoint {
+            endpoint_url: Some("http://localhost:8999".to_string()),
+            use_fips: false,
+            legacy: true,
+        };
+        assert_eq!(
+            endpoint.resolve(Some("us-east-1")),
+            (
+                Some("http://localhost:8999".to_string()),
+                Some("us-east-1".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn validate_duration_for_session_token() {
+        let range = SESSION_TOKEN_DURATION;
+        assert_eq!(
+            validate_duration(900, range, "GetSessionToken").unwrap(),
+            900
+        );
+        assert_eq!(
+            validate_duration(129600, range, "GetSessionToken").unwrap(),
+            129600
+        );
+        assert!(validate_duration(899, range, "GetSessionToken").is_err());
+        assert!(validate_duration(129601, range, "GetSessionToken").is_err());
+    }
+
+    #[test]
+    fn validate_duration_for_assume_role() {
+        let range = assume_role_duration(false);
+        assert_eq!(validate_duration(900, range, "AssumeRole").unwrap(), 900);
+        assert_eq!(
+            validate_duration(43200, range, "AssumeRole").unwrap(),
+            43200
+        );
+        assert!(validate_duration(899, range, "AssumeRole").is_err());
+        assert!(validate_duration(43201, range, "AssumeRole").is_err());
+    }
+
+    #[test]
+    fn validate_duration_for_chained_role() {
+        let range = assume_role_duration(true);
+        assert_eq!(validate_duration(3600, range, "AssumeRole").unwrap(), 3600);
+        assert!(validate_duration(3601, range, "AssumeRole").is_err());
+        assert!(validate_duration(899, range, "AssumeRole").is_err());
+    }
+
+    // `aws-runtime`に依存しないよう`aws-config`の再エクスポートを利用する
+    #[allow(deprecated)]
+    #[tokio::test]
+    async fn sts_config_uses_target_profile() {
+        use aws_config::profile::profile_file::{ProfileFileKind, ProfileFiles};
+
+        // 選択中のプロファイル(ここでは`default`)のエンドポイントとリージョンを引き継がない
+        let files = || {
+            ProfileFiles::builder()
+                .with_contents(
+                    ProfileFileKind::Config,
+                    "[default]\nregion = us-west-2\nendpoint_url = http://127.0.0.1:1\n\n\
+                     [profile b]\nregion = eu-west-1\n",
+                )
+                .build()
+        };
+        let config = profile::configs::Config {
+            name: "b".to_string(),
+            ..Default::default()
+        };
+        let load = |endpoint: StsEndpoint| {
+            sts_config_loader(&config, &endpoint)
+                .empty_test_environment()
+                .profile_files(files())
+                .no_credentials()
+                .load()
+        };
+        let sdk_config = load(StsEndpoint::default()).await;
+        assert_eq!(sdk_config.region().map(|x| x.as_ref()), Some("eu-west-1"));
+        assert_eq!(sdk_config.endpoint_url(), None);
+
+        // 対象のプロファイルの設定は反映する
+        let endpoint = StsEndpoint {
+            endpoint_url: Some("http://127.0.0.1:2".to_string()),
+            ..Default::default()
+        };
+        let sdk_config = load(endpoint).await;
+        assert_eq!(sdk_config.endpoint_url(), Some("http://127.0.0.1:2"));
+    }
+
+    /// 長期のアクセスキーでセッションを取得する場合の失敗情報
+    fn failure() -> Failure<'static> {
+        Failure {
+            profile: "dev",
+            caller: "dev",
+            caller_session: false,
+            token: None,
+            role_arn: None,
+            mfa_serial: Some("arn:aws:iam::123456789012:mfa/user"),
+            mfa_generated: false,
+            mfa_reused: false,
+            region: Some("ap-northeast-1"),
+            duration: 3600,
+        }
+    }
+
+    /// 生成したメッセージ(対処方法がない場合は空文字)
+    fn message(code: &str, message: &str, failure: &Failure) -> String {
+        sts_error_message(code, message, failure).unwrap_or_default()
+    }
+
+    #[test]
+    fn sts_error_message_of_mfa() {
+        let invalid = "MultiFactorAuthentication failed with invalid MFA one time pass code.";
+        assert!(message("AccessDenied", invalid, &failure()).contains("was rejected"));
+
+        let reused = Failure {
+            mfa_reused: true,
+            ..failure()
+        };
+        assert!(message("AccessDenied", invalid, &reused).contains("was already used"));
+
+        // シードから生成したコードは再利用ではなく時刻のずれを案内する
+        let generated = Failure {
+            mfa_generated: true,
+            mfa_reused: true,
+            ..failure()
+        };
+        assert!(message("AccessDenied", invalid, &generated).contains("awsst mfa enroll"));
+
+        let serial = "MultiFactorAuthentication failed, unable to validate MFA code. \
+            Please verify your MFA serial number is valid and associated with this user.";
+        assert!(message("AccessDenied", serial, &failure()).contains("is not associated"));
+    }
+
+    #[test]
+    fn sts_error_message_of_invalid_caller() {
+        let text = message("InvalidClientTokenId", "", &failure());
+        assert!(text.contains("access key of profile [dev] is invalid"));
+        assert!(text.contains("awsst update -p dev"));
+
+        // 連鎖元のセッションで署名した場合は連鎖元のセッションを取り直す
+        let chained = Failure {
+            profile: "spoke",
+            caller: "hub",
+            caller_session: true,
+            ..failure()
+        };
+        let text = message("InvalidClientTokenId", "", &chained);
+        assert!(text.contains("session of profile [hub] is no longer valid"));
+        assert!(text.contains("awsst session -p hub -f"));
+
+        let text = message("ExpiredToken", "", &chained);
+        assert!(text.contains("session of profile [hub] has expired"));
+        let web_identity = Failure {
+            token: Some("web identity token"),
+            ..failure()
+        };
+        let text = message("ExpiredTokenException", "", &web_identity);
+        assert!(text.contains("web identity token of profile [dev] has expired"));
+    }
+
+    #[test]
+    fn sts_error_message_of_role() {
+        let role = Failure {
+            role_arn: Some("arn:aws:iam::123456789012:role/Admin"),
+            ..failure()
+        };
+        let text = message("AccessDenied", "not authorized to perform", &role);
+        assert!(text.contains("not allowed to assume role [arn:aws:iam::123456789012:role/Admin]"));
+        // ロールを利用しない場合のAccessDeniedは対処方法を判断できない
+        assert_eq!(
+            sts_error_message("AccessDenied", "denied", &failure()),
+            None
+        );
+
+        let text = message("RegionDisabledException", "", &failure());
+        assert!(text.contains("region [ap-northeast-1]"));
+
+        let exceeds =
+            "The requested DurationSeconds exceeds the MaxSessionDuration set for this role.";
+        let text = message("ValidationError", exceeds, &role);
+        assert!(text.contains("duration 3600s exceeds"));
+        assert_eq!(sts_error_message("ValidationError", "other", &role), None);
+    }
+
+    #[test]
+    fn sts_error_message_of_unknown_error() {
+        assert_eq!(
+            sts_error_message("Throttling", "Rate exceeded", &failure()),
+            None
+        );
+        assert_eq!(sts_error_message("", "", &failure()), None);
+    }
+
+    /// `role_session_name`のテンプレートを設定したプロファイル
+    fn session_name_config(template: Option<&str>) -> profile::configs::Config {
+        let mut config = profile::configs::Config {
+            name: "dev".to_string(),
+            ..Default::default()
+        };
+        if let Some(template) = template {
+            config.others.insert(
+                profile::configs::ROLE_SESSION_NAME_KEY.to_string(),
+                template.to_string(),
+            );
+        }
+        config
+    }
+
+    #[test]
+    fn role_session_name_of_template() {
+        let arn = RoleArn::parse("arn:aws:iam::123456789012:role/Admin").unwrap();
+        // 未設定の場合はロール名を利用する
+        let name = role_session_name(&session_name_config(None), &arn).unwrap();
+        assert_eq!(name, "Admin");
+        let config = session_name_config(Some("awsst/{profile}/{role}"));
+        assert_eq!(role_session_name(&config, &arn).unwrap(), "awsst-dev-Admin");
+
+        let config = session_name_config(Some("{profile}-{account}"));
+        let error = role_session_name(&config, &arn).unwrap_err().to_string();
+        assert!(error.contains("unknown placeholder {account}"));
+    }
+
+    #[test]
+    fn sanitize_session_name_for_sts() {
+        assert_eq!(
+            sanitize_session_name("user@example.com"),
+            "user@example.com"
+        );
+        // 利用できない文字は`-`に置換する
+        assert_eq!(sanitize_session_name("山田 taro/dev"), "---taro-dev");
+        // 64文字を超える場合は切り詰める
+        assert_eq!(sanitize_session_name(&"a".repeat(80)), "a".repeat(64));
+        // 2文字未満の場合は補う
+        assert_eq!(sanitize_session_name("a"), "a-awsst");
+        assert_eq!(sanitize_session_name(""), "-awsst");
+    }
 }
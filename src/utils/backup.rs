@@ -0,0 +1,89 @@
+use std::{fs, path::Path, path::PathBuf};
+
+use chrono::{Local, NaiveDateTime};
+
+/// バックアップを保存するディレクトリ名
+const BACKUP_DIR_NAME: &str = "awsst-backups";
+/// ファイルごとに保持するバックアップの最大数
+const MAX_BACKUPS: usize = 10;
+/// バックアップ名に付与する日時の形式
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+/// バックアップファイルの情報
+#[derive(Debug, Clone)]
+pub struct Backup {
+    // `credentials.20240401-000000-000`形式の識別子
+    pub id: String,
+    // バックアップ元のファイル名
+    pub file_name: String,
+    pub path: PathBuf,
+    pub created: NaiveDateTime,
+}
+
+impl Backup {
+    /// バックアップのファイル名から生成
+    fn from_path(path: PathBuf) -> Option<Backup> {
+        let id = path.file_name()?.to_str()?.to_string();
+        let (file_name, timestamp) = id.split_once('.')?;
+        let created = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+        Some(Backup {
+            file_name: file_name.to_string(),
+            id,
+            path,
+            created,
+        })
+    }
+
+    /// バックアップの内容を読み込む
+    pub fn read(&self) -> std::io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+/// バックアップディレクトリのパスを取得
+pub fn backup_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(super::file::aws_dir()?.join(BACKUP_DIR_NAME))
+}
+
+/// 書き込み前のファイルのバックアップを作成し、古いバックアップを削除する
+pub fn snapshot(file_name: &str, source: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = backup_dir()?;
+    super::file::create_dir(&dir)?;
+
+    // 認証情報を含むため、コピー後に所有者のみが読み書きできる権限にする
+    let id = format!("{}.{}", file_name, Local::now().format(TIMESTAMP_FORMAT));
+    let path = dir.join(id);
+    fs::copy(source, &path)?;
+    super::file::restrict_permissions(&path, 0o600)?;
+
+    // 保持数を超えた古いバックアップを削除
+    for old in list(Some(file_name))?.iter().skip(MAX_BACKUPS) {
+        fs::remove_file(&old.path)?;
+    }
+    Ok(())
+}
+
+/// バックアップの一覧を新しい順に取得
+pub fn list(file_name: Option<&str>) -> Result<Vec<Backup>, Box<dyn std::error::Error>> {
+    let dir = backup_dir()?;
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = Vec::<Backup>::new();
+    for entry in fs::read_dir(dir)? {
+        let Some(backup) = Backup::from_path(entry?.path()) else {
+            continue;
+        };
+        if file_name.is_none_or(|name| name == backup.file_name) {
+            backups.push(backup);
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    Ok(backups)
+}
+
+/// 指定の識別子のバックアップを取得
+pub fn find(id: &str) -> Result<Option<Backup>, Box<dyn std::error::Error>> {
+    Ok(list(None)?.into_iter().find(|backup| backup.id == id))
+}
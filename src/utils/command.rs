@@ -0,0 +1,37 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// 対象のプロファイル名を外部コマンドに渡す環境変数
+pub const PROFILE_ENV: &str = "AWSST_PROFILE";
+
+/// シェル経由で外部コマンドを実行し、標準出力を返却
+/// 環境変数`AWSST_PROFILE`に`profile`を設定し、`input`が指定されている場合は標準入力に渡す
+/// 失敗した場合は標準エラー出力の内容をエラーとする
+pub fn run(command: &str, profile: &str, input: Option<&str>) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env(PROFILE_ENV, profile)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let (Some(text), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    String::from_utf8(output.stdout).map_err(|e| e.to_string())
+}
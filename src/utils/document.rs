@@ -0,0 +1,616 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// ファイルの解析エラー
+/// 行番号と該当行の内容を保持し、どこを修正すればよいかを表示できるようにする
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    // 解析したファイルのパス
+    pub file: Option<PathBuf>,
+    // 1始まりの行番号
+    pub line: usize,
+    // 該当行の内容
+    pub text: String,
+    pub message: String,
+}
+
+impl ParseError {
+    /// 解析したファイルのパスを設定
+    pub fn with_file(mut self, file: &Path) -> ParseError {
+        self.file = Some(file.to_path_buf());
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = match &self.file {
+            Some(path) => path.display().to_string(),
+            None => "<input>".to_string(),
+        };
+        write!(
+            f,
+            "Failed to parse [{}] line {}: {}\n  {} | {}",
+            file, self.line, self.message, self.line, self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// AWSの`config`や`credentials`ファイルのドキュメント
+/// 読み込んだ内容を行単位で保持し、書き込み時には変更のあった行のみを更新する
+#[derive(Debug, Clone)]
+pub struct AWSDocument {
+    // 最初のセクションより前の行(コメントや空行)
+    preamble: Vec<Line>,
+    // ファイル内の順序を保持したセクションのリスト
+    sections: Vec<Section>,
+    // ファイル末尾が改行で終わっているか
+    trailing_newline: bool,
+}
+
+/// `[name]`で始まるセクション
+#[derive(Debug, Clone)]
+struct Section {
+    name: String,
+    // ヘッダの直前のコメント行
+    // セクションの説明として扱い、セクションを削除する場合は合わせて削除する
+    leading: Vec<Line>,
+    header: String,
+    lines: Vec<Line>,
+}
+
+/// ドキュメント内の1行
+#[derive(Debug, Clone)]
+enum Line {
+    // `key = value`形式の行
+    // ネストされたキーは`parent.key`の形式で保持する
+    Entry {
+        raw: String,
+        key: String,
+        value: String,
+    },
+    // `s3 =`のようにネストされたキーを持つ親の行
+    Parent {
+        raw: String,
+        key: String,
+    },
+    // コメントや空行など、そのまま書き戻す行
+    Other(String),
+}
+
+impl Default for AWSDocument {
+    fn default() -> Self {
+        AWSDocument {
+            preamble: vec![],
+            sections: vec![],
+            trailing_newline: true,
+        }
+    }
+}
+
+impl AWSDocument {
+    /// ファイルの内容からドキュメントを生成
+    pub fn parse(text: &str) -> Result<AWSDocument, ParseError> {
+        let mut document = AWSDocument {
+            trailing_newline: text.is_empty() || text.ends_with('\n'),
+            ..Default::default()
+        };
+
+        // 改行で分割し、末尾の改行による空要素は除外する
+        let mut lines = text.split('\n').collect::<Vec<&str>>();
+        if document.trailing_newline {
+            lines.pop();
+        }
+
+        // ネストされたキーを持つ可能性のある、値が空のキー
+        let mut parent: Option<String> = None;
+        for (index, raw) in lines.into_iter().enumerate() {
+            let error = |message: &str| ParseError {
+                file: None,
+                line: index + 1,
+                text: raw.to_string(),
+                message: message.to_string(),
+            };
+
+            // セクションの開始行の場合は新たなセクションを追加
+            if let Some(name) = Line::section_name(raw) {
+                if name.is_empty() {
+                    return Err(error("section name is empty"));
+                }
+                let leading = match document.sections.last_mut() {
+                    Some(section) => section.take_leading_comments(),
+                    None => vec![],
+                };
+                document.sections.push(Section {
+                    name,
+                    leading,
+                    header: raw.to_string(),
+                    lines: vec![],
+                });
+                parent = None;
+                continue;
+            }
+
+            let mut line = Line::parse(raw);
+            let indented = raw.starts_with([' ', '\t']);
+            match &line {
+                Line::Entry { .. } if document.sections.is_empty() => {
+                    return Err(error("`key = value` must be placed after a section header"));
+                }
+                Line::Entry { key, .. } if key.is_empty() => {
+                    return Err(error("key is empty"));
+                }
+                // インデントされた行は前の値の継続行として扱う
+                Line::Other(raw) if !indented && !Line::is_blank_or_comment(raw) => {
+                    return Err(error("expected `[section]` or `key = value`"));
+                }
+                _ => {}
+            }
+
+            let container = match document.sections.last_mut() {
+                Some(section) => &mut section.lines,
+                None => &mut document.preamble,
+            };
+            match (&mut line, &parent) {
+                // 値が空のキーに続くインデントされた行はネストされたキーとして扱う
+                (Line::Entry { key, .. }, Some(p)) if indented => {
+                    *key = format!("{}.{}", p, key);
+                    Line::to_parent(container, p);
+                }
+                (Line::Entry { key, value, .. }, _) if !indented => {
+                    parent = Some(key.clone()).filter(|_| value.is_empty());
+                }
+                (Line::Other(raw), _) if raw.trim().is_empty() => parent = None,
+                _ => {}
+            }
+            container.push(line);
+        }
+        Ok(document)
+    }
+
+    /// セクション名をキーとしたMap形式に変換
+    /// 同一名のセクションが複数存在する場合は後勝ちで結合する
+    pub fn to_map(&self) -> HashMap<String, HashMap<String, String>> {
+        let mut map = HashMap::<String, HashMap<String, String>>::new();
+        for section in &self.sections {
+            let data = map.entry(section.name.clone()).or_default();
+            for (key, value) in section.entries() {
+                data.insert(key, value);
+            }
+        }
+        map
+    }
+
+    /// 書き込むデータをドキュメントに反映する
+    /// データに存在しないセクションは削除し、値が変わっていない行は元の内容のまま保持する
+    pub fn merge(&mut self, data: &[(String, Vec<(String, String)>)]) {
+        // 末尾のセクションを削除する場合は、区切りの空行も合わせて削除する
+        let last_removed = match self.sections.split_last() {
+            Some((last, others)) => {
+                !data.iter().any(|(name, _)| *name == last.name)
+                    || others.iter().any(|section| section.name == last.name)
+            }
+            None => false,
+        };
+
+        // データに存在しないセクションと重複したセクションを削除
+        let mut seen = Vec::<String>::new();
+        self.sections.retain(|section| {
+            if seen.contains(&section.name) || !data.iter().any(|(name, _)| *name == section.name) {
+                return false;
+            }
+            seen.push(section.name.clone());
+            true
+        });
+        if last_removed {
+            let last = match self.sections.last_mut() {
+                Some(section) => &mut section.lines,
+                None => &mut self.preamble,
+            };
+            while matches!(last.last(), Some(Line::Other(raw)) if raw.trim().is_empty()) {
+                last.pop();
+            }
+        }
+
+        for (name, entries) in data {
+            if let Some(section) = self.sections.iter_mut().find(|s| s.name == *name) {
+                section.merge(entries);
+                continue;
+            }
+
+            // 新たなセクションは空行で区切って末尾に追加する
+            let has_section = !self.sections.is_empty();
+            let last = match self.sections.last_mut() {
+                Some(section) => &mut section.lines,
+                None => &mut self.preamble,
+            };
+            let need_blank = match last.last() {
+                Some(Line::Other(raw)) => !raw.trim().is_empty(),
+                Some(_) => true,
+                None => has_section,
+            };
+            if need_blank {
+                last.push(Line::Other(String::new()));
+            }
+
+            let mut section = Section {
+                name: name.clone(),
+                leading: vec![],
+                header: format!("[{}]", name),
+                lines: vec![],
+            };
+            section.merge(entries);
+            self.sections.push(section);
+        }
+    }
+}
+
+impl Display for AWSDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = Vec::<&str>::new();
+        lines.extend(self.preamble.iter().map(|l| l.raw()));
+        for section in &self.sections {
+            lines.extend(section.leading.iter().map(|l| l.raw()));
+            lines.push(section.header.as_str());
+            lines.extend(section.lines.iter().map(|l| l.raw()));
+        }
+
+        write!(f, "{}", lines.join("\n"))?;
+        if self.trailing_newline && !lines.is_empty() {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Section {
+    /// セクション内の`key = value`の一覧
+    fn entries(&self) -> Vec<(String, String)> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry { key, value, .. } => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 末尾の連続したコメント行を取り出す
+    /// 次のセクションのヘッダの直前にあるコメントは、そのセクションの説明として扱う
+    fn take_leading_comments(&mut self) -> Vec<Line> {
+        let count = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, Line::Other(raw) if Line::is_comment(raw)))
+            .count();
+        self.lines.split_off(self.lines.len() - count)
+    }
+
+    /// 書き込むデータをセクションに反映する
+    fn merge(&mut self, entries: &[(String, String)]) {
+        // 不要になったキーの行を削除
+        self.lines.retain(|line| match line {
+            Line::Entry { key, .. } => entries.iter().any(|(k, _)| k == key),
+            Line::Parent { key, .. } => {
+                let prefix = format!("{}.", key);
+                entries.iter().any(|(k, _)| k.starts_with(&prefix))
+            }
+            Line::Other(_) => true,
+        });
+
+        for (key, value) in entries {
+            let current = self
+                .lines
+                .iter_mut()
+                .find(|line| matches!(line, Line::Entry { key: k, .. } if k == key));
+            if let Some(Line::Entry {
+                raw, value: old, ..
+            }) = current
+            {
+                // 値が変わった場合のみ、インデントとキーの表記を保持したまま行を書き換える
+                if old != value {
+                    let name = raw.split_once('=').map_or(raw.as_str(), |(n, _)| n);
+                    *raw = format!("{} = {}", name.trim_end(), value);
+                    *old = value.clone();
+                }
+                continue;
+            }
+
+            // ARNのように`.`を含む値をキーとする場合は、ネストされたキーとして扱わない
+            match key
+                .split_once('.')
+                .filter(|(parent, _)| Line::is_key_name(parent))
+            {
+                Some((parent, child)) => self.insert_nested(parent, child, key, value),
+                None => {
+                    // 存在しないキーは最後のエントリ行の直後に追加
+                    let pos = self.end_of_entries();
+                    self.lines.insert(pos, Line::entry("", key, key, value));
+                }
+            }
+        }
+    }
+
+    /// 最後のエントリ行の直後の位置
+    fn end_of_entries(&self) -> usize {
+        self.lines
+            .iter()
+            .rposition(|line| !matches!(line, Line::Other(_)))
+            .map_or(0, |i| i + 1)
+    }
+
+    /// ネストされたキーを親キーのブロックの末尾に追加
+    fn insert_nested(&mut self, parent: &str, child: &str, key: &str, value: &str) {
+        // 親キーの行が存在しない場合は追加
+        let parent_pos = match self.lines.iter().position(|line| {
+            matches!(line, Line::Parent { key: k, .. } | Line::Entry { key: k, .. } if k == parent)
+        }) {
+            Some(pos) => pos,
+            None => {
+                let pos = self.end_of_entries();
+                self.lines.insert(
+                    pos,
+                    Line::Parent {
+                        raw: format!("{} =", parent),
+                        key: parent.to_string(),
+                    },
+                );
+                pos
+            }
+        };
+        Line::to_parent(&mut self.lines, parent);
+
+        // 既存の子キーの後ろに、同じインデントで追加する
+        let prefix = format!("{}.", parent);
+        let mut pos = parent_pos + 1;
+        let mut indent = "  ".to_string();
+        while let Some(Line::Entry { raw, key: k, .. }) = self.lines.get(pos) {
+            if !k.starts_with(&prefix) {
+                break;
+            }
+            indent = raw[..raw.len() - raw.trim_start().len()].to_string();
+            pos += 1;
+        }
+        self.lines
+            .insert(pos, Line::entry(&indent, child, key, value));
+    }
+}
+
+impl Line {
+    /// `[name]`形式の行であればセクション名を返却
+    fn section_name(raw: &str) -> Option<String> {
+        let trimmed = raw.trim();
+        if !trimmed.starts_with('[') {
+            return None;
+        }
+        let end = trimmed.rfind(']')?;
+        Some(trimmed[1..end].trim().to_string())
+    }
+
+    /// 空行またはコメント行か判定
+    fn is_blank_or_comment(raw: &str) -> bool {
+        raw.trim().is_empty() || Line::is_comment(raw)
+    }
+
+    /// コメント行か判定
+    fn is_comment(raw: &str) -> bool {
+        let trimmed = raw.trim();
+        trimmed.starts_with('#') || trimmed.starts_with(';')
+    }
+
+    /// `s3`のようなネストされたキーの親になり得るキー名か判定
+    fn is_key_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    /// 1行分の文字列を解析
+    fn parse(raw: &str) -> Line {
+        // 空行やコメント行はそのまま保持
+        if Line::is_blank_or_comment(raw) {
+            return Line::Other(raw.to_string());
+        }
+        let trimmed = raw.trim();
+
+        // 最初の`=`で分割する
+        // Base64変換された認証情報は値に`=`が含まれるため、以降はすべて値として扱う
+        match trimmed.split_once('=') {
+            Some((key, value)) => Line::Entry {
+                raw: raw.to_string(),
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            },
+            None => Line::Other(raw.to_string()),
+        }
+    }
+
+    /// 新たに追加する`key = value`の行を生成
+    fn entry(indent: &str, name: &str, key: &str, value: &str) -> Line {
+        Line::Entry {
+            raw: format!("{}{} = {}", indent, name, value),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// 値が空のキーの行をネストされたキーを持つ親の行に変換
+    fn to_parent(lines: &mut [Line], parent: &str) {
+        let target = lines
+            .iter_mut()
+            .rev()
+            .find(|line| matches!(line, Line::Entry { key, .. } if key == parent));
+        if let Some(line) = target {
+            *line = Line::Parent {
+                raw: line.raw().to_string(),
+                key: parent.to_string(),
+            };
+        }
+    }
+
+    /// 書き込み用の文字列
+    fn raw(&self) -> &str {
+        match self {
+            Line::Entry { raw, .. } => raw,
+            Line::Parent { raw, .. } => raw,
+            Line::Other(raw) => raw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 解析して書き戻した内容が元の内容と一致するか確認
+    fn assert_round_trip(text: &str) {
+        let document = AWSDocument::parse(text).unwrap();
+        assert_eq!(document.to_string(), text);
+    }
+
+    /// ドキュメントのセクションを書き込み用のデータに変換
+    fn data_of(document: &AWSDocument) -> Vec<(String, Vec<(String, String)>)> {
+        document
+            .sections
+            .iter()
+            .map(|section| (section.name.clone(), section.entries()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_keeps_comments_and_blank_lines() {
+        assert_round_trip(
+            "# header comment\n\n[default]\n; inline style\nregion = us-east-1 \n\n\n[profile a]\n  output=json\n# trailing\n",
+        );
+    }
+
+    #[test]
+    fn round_trip_without_trailing_newline() {
+        assert_round_trip("[default]\nregion = us-east-1");
+        assert_round_trip("");
+    }
+
+    #[test]
+    fn round_trip_nested_and_duplicate_sections() {
+        assert_round_trip(
+            "[profile a]\ns3 =\n  max_concurrent_requests = 20\n  addressing_style = path\nregion = us-east-1\n\n[profile a]\noutput = json\n",
+        );
+    }
+
+    #[test]
+    fn parse_nested_keys() {
+        let document =
+            AWSDocument::parse("[profile a]\ns3 =\n  max_concurrent_requests = 20\n").unwrap();
+        let map = document.to_map();
+        assert_eq!(
+            map["profile a"].get("s3.max_concurrent_requests"),
+            Some(&"20".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_reports_line_number() {
+        let error = AWSDocument::parse("[a]\nkey = 1\nnot an entry\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(AWSDocument::parse("key = 1\n").is_err());
+        assert!(AWSDocument::parse("[]\n").is_err());
+    }
+
+    #[test]
+    fn merge_without_changes_is_identical() {
+        let text = "# comment\n[a]\nkey = 1   \nother=2\n\n[b]\ns3 =\n  x = 1\n";
+        let mut document = AWSDocument::parse(text).unwrap();
+        let data = data_of(&document);
+        document.merge(&data);
+        assert_eq!(document.to_string(), text);
+    }
+
+    #[test]
+    fn merge_keeps_unknown_keys_of_untouched_sections() {
+        let text = "[a]\nregion = ap-northeast-1\nassumed_role = false\n\n[b]\nkey = 1\n";
+        let mut document = AWSDocument::parse(text).unwrap();
+        let mut data = data_of(&document);
+        data[1].1 = vec![("key".to_string(), "2".to_string())];
+        document.merge(&data);
+        assert_eq!(
+            document.to_string(),
+            "[a]\nregion = ap-northeast-1\nassumed_role = false\n\n[b]\nkey = 2\n"
+        );
+    }
+
+    #[test]
+    fn merge_removes_section_with_its_leading_comment() {
+        let text = "[a]\nkey = 1\n# comment of a\n\n# comment of b\n[b]\nkey = 2\n\n[c]\nkey = 3\n";
+        let mut document = AWSDocument::parse(text).unwrap();
+        let data = data_of(&document)
+            .into_iter()
+            .filter(|(name, _)| name != "b")
+            .collect::<Vec<_>>();
+        document.merge(&data);
+        assert_eq!(
+            document.to_string(),
+            "[a]\nkey = 1\n# comment of a\n\n[c]\nkey = 3\n"
+        );
+    }
+
+    #[test]
+    fn merge_removes_last_section_and_separator() {
+        let mut document = AWSDocument::parse("[a]\nkey = 1\n\n[b]\nkey = 2\n").unwrap();
+        let data = vec![("a".to_string(), vec![("key".to_string(), "1".to_string())])];
+        document.merge(&data);
+        assert_eq!(document.to_string(), "[a]\nkey = 1\n");
+    }
+
+    #[test]
+    fn merge_adds_keys_and_sections() {
+        let mut document = AWSDocument::parse("[a]\nkey = 1\n# note\n").unwrap();
+        let data = vec![
+            (
+                "a".to_string(),
+                vec![
+                    ("key".to_string(), "1".to_string()),
+                    ("new".to_string(), "2".to_string()),
+                    ("s3.addressing_style".to_string(), "path".to_string()),
+                ],
+            ),
+            ("b".to_string(), vec![("key".to_string(), "3".to_string())]),
+        ];
+        document.merge(&data);
+        assert_eq!(
+            document.to_string(),
+            "[a]\nkey = 1\nnew = 2\ns3 =\n  addressing_style = path\n# note\n\n[b]\nkey = 3\n"
+        );
+    }
+
+    #[test]
+    fn merge_adds_arn_keys_without_nesting() {
+        let arn = "arn:aws:iam::123456789012:mfa/john.doe";
+        let mut document = AWSDocument::parse("[mfa]\n").unwrap();
+        let data = vec![("mfa".to_string(), vec![(arn.to_string(), "1".to_string())])];
+        document.merge(&data);
+        let text = document.to_string();
+        assert_eq!(text, format!("[mfa]\n{} = 1\n", arn));
+
+        // 書き戻した内容を読み込んでも同じキーとして扱われる
+        let mut document = AWSDocument::parse(&text).unwrap();
+        assert_eq!(data_of(&document), data);
+        let data = vec![("mfa".to_string(), vec![(arn.to_string(), "2".to_string())])];
+        document.merge(&data);
+        assert_eq!(document.to_string(), format!("[mfa]\n{} = 2\n", arn));
+    }
+
+    #[test]
+    fn merge_removes_duplicate_sections() {
+        let mut document = AWSDocument::parse("[a]\nkey = 1\n\n[a]\nkey = 2\n").unwrap();
+        let data = vec![("a".to_string(), vec![("key".to_string(), "2".to_string())])];
+        document.merge(&data);
+        assert_eq!(document.to_string(), "[a]\nkey = 2\n");
+    }
+}
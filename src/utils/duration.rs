@@ -0,0 +1,97 @@
+/// 期間の単位と秒数
+const UNITS: [(char, i64); 4] = [('s', 1), ('m', 60), ('h', 3600), ('d', 86400)];
+
+/// `900`や`15m`, `12h`, `1h30m`などの形式の期間を秒数に変換
+/// 単位を省略した場合は秒として扱う
+pub fn parse_seconds(text: &str) -> Result<i32, String> {
+    let text = text.trim();
+    let invalid = || {
+        format!(
+            "invalid duration [{}]. Use seconds or a combination of `d`, `h`, `m` and `s` such as `1h30m`",
+            text
+        )
+    };
+    if text.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        // 数字の後に単位が続く形式のみ許可する
+        let Some((_, unit)) = UNITS.iter().find(|(u, _)| *u == c.to_ascii_lowercase()) else {
+            return Err(invalid());
+        };
+        let value = number.parse::<i64>().map_err(|_| invalid())?;
+        total = total.saturating_add(value.saturating_mul(*unit));
+        number.clear();
+    }
+    if !number.is_empty() {
+        total = total.saturating_add(number.parse::<i64>().map_err(|_| invalid())?);
+    }
+
+    i32::try_from(total).map_err(|_| invalid())
+}
+
+/// 秒数を`1h30m`のような形式に変換
+pub fn format_seconds(seconds: i64) -> String {
+    if seconds <= 0 {
+        return "0s".to_string();
+    }
+    let mut rest = seconds;
+    let mut text = String::new();
+    for (unit, value) in UNITS.iter().rev() {
+        if rest >= *value {
+            text.push_str(&format!("{}{}", rest / value, unit));
+            rest %= value;
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_seconds_without_unit() {
+        assert_eq!(parse_seconds("3600"), Ok(3600));
+        assert_eq!(parse_seconds(" 900 "), Ok(900));
+    }
+
+    #[test]
+    fn parse_seconds_with_units() {
+        assert_eq!(parse_seconds("90m"), Ok(5400));
+        assert_eq!(parse_seconds("12h"), Ok(43200));
+        assert_eq!(parse_seconds("1h30m"), Ok(5400));
+        assert_eq!(parse_seconds("1d2H3m4s"), Ok(93784));
+    }
+
+    #[test]
+    fn parse_seconds_rejects_invalid_input() {
+        assert!(parse_seconds("").is_err());
+        assert!(parse_seconds("   ").is_err());
+        assert!(parse_seconds("10w").is_err());
+        assert!(parse_seconds("h").is_err());
+        assert!(parse_seconds("-900").is_err());
+    }
+
+    #[test]
+    fn parse_seconds_rejects_overflow() {
+        assert!(parse_seconds("2147483648").is_err());
+        assert!(parse_seconds("99999999999999999999").is_err());
+        assert!(parse_seconds("100000000d").is_err());
+        assert_eq!(parse_seconds("2147483647"), Ok(i32::MAX));
+    }
+
+    #[test]
+    fn format_seconds_round_trip() {
+        assert_eq!(format_seconds(5400), "1h30m");
+        assert_eq!(format_seconds(0), "0s");
+        assert_eq!(parse_seconds(&format_seconds(93784)), Ok(93784));
+    }
+}
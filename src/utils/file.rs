@@ -1,170 +1,400 @@
 use std::{
-    collections::HashMap,
-    fs,
-    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write},
+    env, fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
-use super::AWSFileManager;
+use super::{backup, document::AWSDocument, prompt::Prompter, AWSFileManager};
+use crate::error::AppError;
+
+/// 複数プロセスからの同時更新を防ぐためのロックファイル
+const LOCK_FILE_NAME: &str = ".awsst.lock";
+
+/// AWS SDKと同様に環境変数でパスを指定できるファイル
+const ENV_FILE_PATHS: [(&str, &str); 2] = [
+    ("config", "AWS_CONFIG_FILE"),
+    ("credentials", "AWS_SHARED_CREDENTIALS_FILE"),
+];
+
+/// `--aws-dir`で指定されたディレクトリ
+static CUSTOM_AWS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// `--dry-run`が指定されているか
+static DRY_RUN: OnceLock<bool> = OnceLock::new();
+
+/// 差分の表示時に値を伏せるキー
+const SECRET_KEYS: [&str; 5] = [
+    "aws_access_key_id",
+    "aws_secret_access_key",
+    "aws_session_token",
+    "aws_security_token",
+    "mfa_totp_seed",
+];
+
+/// `--dry-run`を設定
+/// 設定後はファイルを書き込まずに変更内容の差分を表示する
+pub fn set_dry_run() {
+    let _ = DRY_RUN.set(true);
+}
+
+/// `--dry-run`が指定されているか
+pub fn is_dry_run() -> bool {
+    DRY_RUN.get().copied().unwrap_or(false)
+}
+
+/// `--aws-dir`で指定されたディレクトリを設定
+pub fn set_aws_dir(dir: PathBuf) {
+    let _ = CUSTOM_AWS_DIR.set(dir);
+}
+
+/// `--aws-dir`で指定されたディレクトリを取得
+pub fn custom_aws_dir() -> Option<&'static PathBuf> {
+    CUSTOM_AWS_DIR.get()
+}
+
+/// AWS関連ファイルの読み込みから書き込みまでを保護するアドバイザリロック
+/// ドロップ時にロックを解放する
+/// `--dry-run`の場合はロックファイルを作成しないため`None`
+pub struct FileLock {
+    file: Option<fs::File>,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// ロックを取得する
+/// 他のプロセスがロックを保持している場合は解放されるまで待機する
+pub fn lock(prompter: &mut Prompter) -> Result<FileLock, Box<dyn std::error::Error>> {
+    // `--dry-run`の場合は書き込まないため、ロックファイルも作成しない
+    if is_dry_run() {
+        return Ok(FileLock { file: None });
+    }
+    let path = aws_file_path(LOCK_FILE_NAME)?;
+    let file = private_options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+
+    if file.try_lock().is_err() {
+        prompter.standard("Waiting for another awsst process to finish...");
+        file.lock()?;
+    }
+    Ok(FileLock { file: Some(file) })
+}
+
+/// AWS関連のファイルを配置するディレクトリを取得
+/// `--aws-dir`の指定がない場合は`~/.aws`を利用する
+pub fn aws_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(dir) = custom_aws_dir() {
+        return Ok(dir.clone());
+    }
 
-/// ファイル読み込み
-pub fn read<T, S>(file_name: &str) -> Result<T, Box<dyn std::error::Error>>
-where
-    T: AWSFileManager<S>,
-{
     // ホームディレクトリを取得
     let opt_home = dirs::home_dir();
-    // ホームディレクトリが存在しない場合は作成するかの確認メッセージを表示
+    // ホームディレクトリが存在しない場合はエラー
     if opt_home.is_none() {
-        return Err(Box::new(Error::new(
-            ErrorKind::Other,
-            "Oops... does not home directory..orz",
+        return Err(Box::new(AppError::File(
+            "Oops... does not home directory..orz".to_string(),
         )));
     }
     // awsディレクトリを生成
     let mut aws_dir = opt_home.unwrap();
     aws_dir.push(".aws");
+    Ok(aws_dir)
+}
 
-    // 存在確認を行い、存在しない場合は作成するかの確認メッセージを表示
-    if !aws_dir.exists() {
-        return Err(Box::new(Error::new(
-            ErrorKind::Other,
-            "Oops... does not [~/.aws] directory..orz",
-        )));
-    }
+/// ファイル名に対応するパスを取得
+/// `--aws-dir`の指定がない場合、`config`と`credentials`は環境変数での指定を優先する
+pub fn aws_file_path(file_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let env_path = ENV_FILE_PATHS
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .and_then(|(_, key)| env::var(key).ok())
+        .filter(|val| !val.is_empty());
 
     // ファイルのパスを生成
-    let mut config_path = aws_dir;
-    config_path.push(file_name);
-    // ファイルの存在確認し、存在しない場合は空で作成しておく
-    if !config_path.exists() {
-        let mut f = BufWriter::new(fs::File::create(config_path.clone())?);
-        f.write_all("".as_bytes())?;
-    }
+    let fullpath = match env_path {
+        Some(val) if custom_aws_dir().is_none() => expand_home(&val),
+        _ => aws_dir()?.join(file_name),
+    };
 
-    // ファイルを読み込み
-    let file = fs::File::open(config_path)?;
-    let reader = BufReader::new(file);
-    // 読み込んだファイルをHashMap形式に整形
-    let result = shape_aws_toml(reader)?;
-    Ok(T::new(result))
+    // 存在確認を行い、存在しない場合はディレクトリを作成
+    // `--dry-run`の場合は何も作成しない
+    if let Some(dir) = fullpath.parent().filter(|_| !is_dry_run()) {
+        create_dir(dir)?;
+    }
+    Ok(fullpath)
 }
 
-/// AWS関連のファイルデータをMap形式に整形する
-fn shape_aws_toml(
-    reader: BufReader<fs::File>,
-) -> std::io::Result<HashMap<String, HashMap<String, String>>> {
-    // 読み込んだデータ用のマップ
-    let mut tomls = HashMap::new();
-
-    // プロファイルの名称を取得するための正規表現
-    let re = regex::Regex::new(r"\[(.*)\]").unwrap();
-
-    // 現在処理中のキー名と格納用マップ
-    let mut key_name: Option<String> = None;
+/// 先頭の`~`をホームディレクトリに置き換える
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
 
-    // バッファから1行ずつ読み込んで処理する
-    for line in reader.lines() {
-        // ライン読み込み
-        let l = line?;
+/// ディレクトリが存在しない場合は所有者のみアクセスできる権限で作成
+pub fn create_dir(dir: &Path) -> std::io::Result<()> {
+    if dir.as_os_str().is_empty() || dir.exists() {
+        return Ok(());
+    }
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
 
-        // トリムした結果、空行であった場合は無視
-        if l.as_str().trim().is_empty() {
-            continue;
-        }
+/// 新たに作成するファイルを所有者のみが読み書きできる権限にする
+fn private_options() -> fs::OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+}
 
-        // 名称の行と一致する場合
-        if let Some(name) = re.captures(l.as_str()) {
-            let key = name.get(1).unwrap().as_str().to_string();
-            key_name = Some(key.clone());
-            tomls.insert(key, "".to_string());
-            continue;
+/// 所有者以外にも権限が付与されている場合はそのパーミッションを返却
+pub fn loose_mode(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Some(mode);
         }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    None
+}
 
-        // `=`で分割する
-        let c: char = '=';
-        let data = l.split(c).collect::<Vec<&str>>();
-        // 分割できなかった場合は無視
-        if data.len() < 2 {
-            continue;
-        }
-        let key = key_name.clone().unwrap();
-        if let Some(x) = tomls.get_mut(&key) {
-            // 未設定状態の場合はそのまま設定
-            *x = if x.is_empty() {
-                data.join("=")
-            } else {
-                let v = vec![x.clone(), data.join("=")];
-                v.join(";")
-            }
-        }
+/// 所有者以外の権限を取り除く
+/// 権限を変更した場合は`true`を返却
+pub fn restrict_permissions(path: &Path, mode: u32) -> std::io::Result<bool> {
+    #[cfg(unix)]
+    if loose_mode(path).is_some() {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        return Ok(true);
     }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(false)
+}
 
-    // 返却用のMapを生成
-    let mut ret_map = HashMap::new();
-    // 読み込んだデータのキー分実施
-    for (key, val) in tomls.iter() {
-        // キー内の要素を格納するMap
-        let mut data_map = HashMap::new();
-        let c: char = '=';
-        let c2: char = ';';
-        // セミコロンで分割することでキー要素内の個々のデータを取得する
-        val.split(c2).for_each(|x| {
-            // 個々のデータをKey, Value形式に変換してMapに追加
-            let v = x
-                .split(c)
-                .map(|s| s.trim().to_string())
-                .collect::<Vec<String>>();
-            // Base64変換された認証情報は値に`=`が含まれるため、すべてをjoinするようにする
-            let s = v[1..(v.len())].to_vec().join("=");
-            data_map.insert(v[0].clone(), s);
-        });
-        ret_map.insert(key.clone(), data_map);
+/// ファイル読み込み
+pub fn read<T, S>(file_name: &str) -> Result<T, Box<dyn std::error::Error>>
+where
+    T: AWSFileManager<S>,
+{
+    let config_path = aws_file_path(file_name)?;
+    // ファイルの存在確認し、存在しない場合は空で作成しておく
+    if !config_path.exists() && !is_dry_run() {
+        private_options()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(config_path.clone())?;
     }
 
-    Ok(ret_map)
+    // ファイルを読み込み、元の内容を保持したドキュメントに変換
+    let text = if config_path.exists() {
+        fs::read_to_string(&config_path)?
+    } else {
+        String::new()
+    };
+    let document = AWSDocument::parse(&text).map_err(|e| e.with_file(&config_path))?;
+    Ok(T::new(document))
+}
+
+/// ファイルの内容を文字列のまま読み込む
+/// ファイルが存在しない場合は空文字を返却
+pub fn read_text(file_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = aws_file_path(file_name)?;
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    Ok(fs::read_to_string(path)?)
 }
 
 /// ファイル書き込み
+/// `backup`が指定されている場合は書き込み前の内容をバックアップする
 pub fn write(
     file_name: &str,
-    data: HashMap<String, HashMap<String, String>>,
-    add_profile: bool,
+    document: &AWSDocument,
+    backup: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // 書き込み用のデータ
-    let mut write_data = String::new();
-    for (key, ele) in data {
-        // キーを設定
-        if add_profile && key != "default" {
-            write_data += format!("[profile {}]\n", key).as_str();
-        } else {
-            write_data += format!("[{}]\n", key).as_str();
-        }
+    write_text(file_name, document.to_string().as_str(), backup)
+}
 
-        // 要素をそれぞれ追加していく
-        for (ele_key, val) in ele {
-            write_data += format!("{} = {}\n", ele_key, val).as_str();
-        }
-        write_data += "\n";
+/// バックアップの内容でファイルを復元する
+/// 復元前の内容もバックアップしておき、復元を取り消せるようにする
+pub fn restore(backup: &backup::Backup) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = fs::read(&backup.path)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => write_text(&backup.file_name, text, true),
+        // 暗号化した保管庫は差分を表示できないため、`--dry-run`の場合は何もしない
+        Err(_) if is_dry_run() => Ok(()),
+        Err(_) => write_bytes(&backup.file_name, &bytes, true),
     }
+}
 
-    // バイトデータを生成
-    let bytes = write_data.as_bytes();
-
-    // ホームディレクトリを取得
-    let opt_home = dirs::home_dir();
-    // ホームディレクトリが存在しない場合は作成するかの確認メッセージを表示
-    if opt_home.is_none() {
-        return Err(Box::new(Error::new(ErrorKind::Other, "oh no!")));
+/// 文字列をファイルに書き込む
+/// `--dry-run`の場合は書き込まずに差分のみ表示する
+fn write_text(file_name: &str, text: &str, backup: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if is_dry_run() {
+        let current = read_text(file_name)?;
+        return show_dry_run_diff(file_name, &current, text);
     }
-    // awsディレクトリを生成
-    let mut aws_dir = opt_home.unwrap();
-    aws_dir.push(".aws");
+    write_bytes(file_name, text.as_bytes(), backup)
+}
+
+/// `--dry-run`時に書き込む予定の内容との差分を表示する
+/// 認証情報の値は伏せて表示する
+pub fn show_dry_run_diff(
+    file_name: &str,
+    current: &str,
+    new: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = aws_file_path(file_name)?.display().to_string();
+    Prompter::new().diff(
+        &mask_secrets(current),
+        &mask_secrets(new),
+        &name,
+        &format!("{} (dry-run)", name),
+    );
+    Ok(())
+}
+
+/// バイトデータをファイルに書き込む
+/// 同一ディレクトリの一時ファイルに書き込んでから置き換えることで、
+/// 書き込み途中で中断されても元のファイルが壊れないようにする
+pub fn write_bytes(
+    file_name: &str,
+    bytes: &[u8],
+    backup: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     // ファイルのパスを生成
-    let mut fullpath = aws_dir;
-    fullpath.push(file_name);
+    // シンボリックリンクの場合はリンク先のファイルを置き換える
+    let fullpath = aws_file_path(file_name)?;
+    let fullpath = fs::canonicalize(&fullpath).unwrap_or(fullpath);
+    // `sso/cache/xxx.json`のようにディレクトリを含む場合もファイル名のみを利用する
+    let base_name = Path::new(file_name)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = fullpath.with_file_name(format!(".{}.{}.tmp", base_name, std::process::id()));
+
+    // 内容に変更がある場合は書き込み前にバックアップを作成
+    if backup && fullpath.exists() && fs::read(&fullpath)? != bytes {
+        backup::snapshot(file_name, &fullpath)?;
+    }
+
+    let result = write_atomic(&fullpath, &tmp_path, bytes);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// 認証情報の値を末尾4文字以外伏せた文字列に変換
+/// `aws configure`と同様の表示にすることで、値が変わったかどうかは判別できるようにする
+/// 4文字以下の値は末尾を表示すると全て見えてしまうため全て伏せる
+fn mask_secrets(text: &str) -> String {
+    text.split('\n')
+        .map(|line| match line.split_once('=') {
+            Some((key, value)) if SECRET_KEYS.contains(&key.trim()) => {
+                let value = value.trim();
+                let tail = value
+                    .char_indices()
+                    .rev()
+                    .nth(3)
+                    .filter(|(i, _)| *i > 0)
+                    .map_or("", |(i, _)| &value[i..]);
+                format!("{}= {}{}", key, "*".repeat(16), tail)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-    let mut f = BufWriter::new(fs::File::create(fullpath)?);
+/// 一時ファイルへの書き込み、同期、リネームを行う
+fn write_atomic(
+    fullpath: &Path,
+    tmp_path: &Path,
+    bytes: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut f = BufWriter::new(
+        private_options()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(tmp_path)?,
+    );
     f.write_all(bytes)?;
+    let file = f.into_inner().map_err(|e| e.into_error())?;
+    // 既存ファイルのパーミッションを引き継ぐ
+    if let Ok(meta) = fs::metadata(fullpath) {
+        file.set_permissions(meta.permissions())?;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(tmp_path, fullpath)?;
 
+    // リネーム結果をディスクに反映するためディレクトリも同期する
+    #[cfg(unix)]
+    if let Some(Ok(dir)) = fullpath.parent().map(fs::File::open) {
+        let _ = dir.sync_all();
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_secrets_of_credentials() {
+        let text = "[a]\n\
+            aws_secret_access_key = abcdefghijklmnop\n\
+            aws_session_token=token-value-1234\n\
+            mfa_totp_seed = JBSWY3DPEHPK3PXP\n\
+            region = us-east-1\n";
+        assert_eq!(
+            mask_secrets(text),
+            "[a]\n\
+            aws_secret_access_key = ****************mnop\n\
+            aws_session_token= ****************1234\n\
+            mfa_totp_seed = ****************3PXP\n\
+            region = us-east-1\n"
+        );
+    }
+
+    #[test]
+    fn mask_secrets_of_short_values() {
+        // 4文字以下の値は末尾も表示しない
+        assert_eq!(
+            mask_secrets("aws_secret_access_key = abcd"),
+            "aws_secret_access_key = ****************"
+        );
+        assert_eq!(
+            mask_secrets("aws_session_token = ab"),
+            "aws_session_token = ****************"
+        );
+        assert_eq!(
+            mask_secrets("mfa_totp_seed = abcde"),
+            "mfa_totp_seed = ****************bcde"
+        );
+    }
+}
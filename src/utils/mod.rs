@@ -1,13 +1,18 @@
-use std::collections::HashMap;
+use self::document::AWSDocument;
 
+pub mod backup;
+pub mod command;
+pub mod document;
+pub mod duration;
 pub mod file;
 pub mod prompt;
+pub mod totp;
 
 /// AWSの`config`や`credential`ファイル用のトレイト
 pub trait AWSFileManager<T> {
-    fn new(val: HashMap<String, HashMap<String, String>>) -> Self;
+    fn new(document: AWSDocument) -> Self;
 
-    fn to_file(&self) -> HashMap<String, HashMap<String, String>>;
+    fn to_file(&self) -> Vec<(String, Vec<(String, String)>)>;
 
     fn write(&self) -> Result<(), Box<dyn std::error::Error>>;
 
@@ -17,5 +22,5 @@ pub trait AWSFileManager<T> {
 }
 
 pub trait AWSFile {
-    fn to_file_map(&self) -> HashMap<String, String>;
+    fn to_file_map(&self) -> Vec<(String, String)>;
 }
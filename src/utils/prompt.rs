@@ -1,13 +1,16 @@
 use dialoguer::{
     console::{style, Style, Term},
     theme::ColorfulTheme,
-    Confirm, Input, Select,
+    Confirm, Input, Password, Select,
 };
+use similar::TextDiff;
 use std::{
-    io::{Error, ErrorKind, Write},
+    io::{Error, Write},
     str,
 };
 
+use crate::error::AppError;
+
 pub struct Prompter {
     pub term: Term,
     height: usize,
@@ -32,16 +35,22 @@ impl Prompter {
     }
 
     /// 選択用のプロンプトを設定
-    pub fn select_prompt(&self, selections: &[String], msg: &str) -> Option<usize> {
+    /// 選択せずに終了した場合はキャンセルとして扱う
+    pub fn select_prompt(
+        &self,
+        selections: &[String],
+        msg: &str,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
         let result = Select::with_theme(&ColorfulTheme::default())
             .with_prompt(msg)
             .default(0)
             .items(selections)
             .interact_on_opt(&self.term);
-        if result.is_err() {
-            return None;
+        match result {
+            Ok(Some(index)) => Ok(index),
+            Ok(None) => Err(Box::new(AppError::Cancelled)),
+            Err(e) => Err(Box::new(AppError::from_prompt(e))),
         }
-        result.unwrap()
     }
 
     /// テキスト入力用のプロンプトを設定
@@ -67,6 +76,21 @@ impl Prompter {
         Ok(text)
     }
 
+    /// パスワード入力用のプロンプトを設定
+    /// `confirm`が`true`の場合は確認のため2回入力させる
+    pub fn password_prompt(
+        &self,
+        msg: &str,
+        confirm: bool,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let theme = ColorfulTheme::default();
+        let mut password = Password::with_theme(&theme).with_prompt(msg);
+        if confirm {
+            password = password.with_confirmation("Confirm", "Oops... the inputs do not match..");
+        }
+        Ok(password.interact_on(&self.term)?)
+    }
+
     /// 確認用のプロンプトを設定
     pub fn confirm_prompt(&self, msg: &str) -> Result<bool, Box<dyn std::error::Error>> {
         // 確認フォーム表示
@@ -90,7 +114,7 @@ impl Prompter {
         f: F,
     ) -> std::io::Result<()> {
         let mut buf = String::new();
-        f(self, &mut buf).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        f(self, &mut buf).map_err(std::io::Error::other)?;
         self.height += buf.chars().filter(|&x| x == '\n').count() + 1;
         self.term.write_line(&buf)
     }
@@ -108,6 +132,44 @@ impl Prompter {
         });
     }
 
+    /// 2つの文字列の差分をunified形式で表示
+    pub fn diff(&mut self, old: &str, new: &str, old_name: &str, new_name: &str) {
+        let text = TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_name, new_name)
+            .to_string();
+        if text.is_empty() {
+            self.standard("No differences.");
+            return;
+        }
+
+        for line in text.lines() {
+            let _ = self.write_formatted_line(|_, buf| {
+                let style = if line.starts_with("+++") || line.starts_with("---") {
+                    Style::new().for_stderr().bold()
+                } else if line.starts_with("@@") {
+                    Style::new().for_stderr().cyan()
+                } else if line.starts_with('+') {
+                    Style::new().for_stderr().green()
+                } else if line.starts_with('-') {
+                    Style::new().for_stderr().red()
+                } else {
+                    Style::new().for_stderr()
+                };
+                write!(buf, "{}", style.apply_to(line))
+            });
+        }
+    }
+
+    /// 警告の文字列を表示
+    pub fn warning(&mut self, msg: &str) {
+        let _ = self.write_formatted_line(|_, buf| {
+            let prefix = style("!".to_string()).for_stderr().yellow();
+            let style = Style::new().for_stderr().yellow();
+            write!(buf, "{} {}", &prefix, style.apply_to(msg))
+        });
+    }
+
     /// エラーの文字列を表示
     pub fn error(&mut self, msg: &str) {
         let _ = self.write_formatted_line(|_, buf| {
@@ -145,12 +207,7 @@ impl Write for StringWriter {
     fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
         let string = match str::from_utf8(data) {
             Ok(s) => s,
-            Err(e) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("Cannot decode utf8 string : {}", e),
-                ))
-            }
+            Err(e) => return Err(Error::other(format!("Cannot decode utf8 string : {}", e))),
         };
         self.string.push_str(string);
         Ok(data.len())